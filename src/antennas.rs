@@ -9,13 +9,22 @@ use ncollide3d::query::Ray;
 use ncollide3d::query::RayCast;
 use ncollide3d::query::RayIntersection;
 
+use crate::blockage::Blockage;
+use crate::geometry::SceneGeometry;
+use crate::materials::Material;
 use crate::simulation;
-use crate::waves::ABSORBANCE_AIR;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SignalReceiver {
     pub position: Point3<f32>,
     pub transfers: Vec<Vec<SignalEvent>>, // indexed by emitter, (time, gain)
+    /// Indexed by emitter, strongest first. Only recorded when asked in the `TracerConfig`
+    #[serde(default)]
+    pub paths: Vec<Vec<PropagationPath>>,
+    /// Transfer functions at each instant of the timeline of a scene with
+    /// moving objects, indexed by frame then emitter
+    #[serde(default)]
+    pub frames: Vec<Vec<Vec<SignalEvent>>>,
     pub kind: simulation::ReceptionKind,
 }
 
@@ -25,6 +34,14 @@ pub struct SignalEvent {
     pub gain: f32,
 }
 
+/// Interaction points of a ray, from the emitter to the receiver
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PropagationPath {
+    pub points: Vec<Point3<f32>>,
+    pub time: usize,
+    pub gain: f32,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SignalEmitter {
     pub position: Point3<f32>,
@@ -44,35 +61,61 @@ pub struct SerializableWorld {
     pub emitters: Vec<Option<SignalEmitter>>,
     pub receivers: Vec<Option<SignalReceiver>>,
     pub names: Vec<String>,
+    /// See `fingerprint::scene_hash`, absent from older solutions
+    #[serde(default)]
+    pub scene_hash: Option<u64>,
+    /// Instants of the frames in seconds, empty for a static scene
+    #[serde(default)]
+    pub timeline: Vec<f32>,
+    /// Absent from older solutions
+    #[serde(default)]
+    pub metadata: Option<crate::solution::Metadata>,
 }
 
 pub struct SceneObject {
-    geometry: Box<dyn RayCast<f32> + Sync + Send>,
+    geometry: Box<dyn SceneGeometry>,
     transform: Isometry<f32>,
+    material: Material,
+    /// Cached from the material
     pub n: f32,
     pub absorbance: f32,
     pub receiver: Option<usize>,
+    pub blockage: Option<Blockage>,
 }
 
 impl SceneObject {
     pub fn new<G>(
         geometry: Box<G>,
         transform: Isometry<f32>,
-        n: f32,
+        material: Material,
         receiver: Option<usize>,
     ) -> SceneObject
     where
-        G: 'static + Sync + Send + RayCast<f32> + HasBoundingVolume<f32, AABB<f32>>,
+        G: 'static + SceneGeometry + HasBoundingVolume<f32, AABB<f32>>,
     {
         SceneObject {
             geometry,
             transform,
-            n,
-            absorbance: ABSORBANCE_AIR,
-            receiver: receiver,
+            n: material.refractive_index(),
+            absorbance: material.absorbance(),
+            material,
+            receiver,
+            blockage: None,
         }
     }
 
+    pub fn geometry(&self) -> &dyn SceneGeometry {
+        &*self.geometry
+    }
+
+    pub fn transform(&self) -> &Isometry<f32> {
+        &self.transform
+    }
+
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
     pub fn cast(&self, ray: &Ray<f32>) -> Option<RayIntersection<f32>> {
         self.geometry
             .toi_and_normal_with_ray(&self.transform, ray, false)
@@ -106,7 +149,7 @@ impl<'a> BestFirstVisitor<f32, SceneObject, AABB<f32>> for ClosestRayTOICostFn<'
         data: Option<&SceneObject>,
     ) -> BestFirstVisitStatus<f32, Self::Result> {
         let dist = aabb
-            .toi_with_ray(&Isometry::identity(), self.ray, true).unwrap_or(std::f32::INFINITY);
+            .toi_with_ray(&Isometry::identity(), self.ray, true).unwrap_or(f32::INFINITY);
 
         let mut res = BestFirstVisitStatus::Continue {
             cost: dist,
@@ -126,26 +169,53 @@ impl<'a> BestFirstVisitor<f32, SceneObject, AABB<f32>> for ClosestRayTOICostFn<'
     }
 }
 
-pub fn create_bvt_tuple<G>(shape: &G, transform: Isometry<f32>, n: f32) -> (SceneObject, AABB<f32>)
+pub fn create_bvt_tuple<G>(
+    shape: &G,
+    transform: Isometry<f32>,
+    material: Material,
+) -> (SceneObject, AABB<f32>)
 where
-    G: 'static + Send + Sync + Clone + RayCast<f32> + HasBoundingVolume<f32, AABB<f32>>,
+    G: 'static + Clone + SceneGeometry + HasBoundingVolume<f32, AABB<f32>>,
 {
     (
-        SceneObject::new(Box::new(shape.clone()), transform, n, None),
+        SceneObject::new(Box::new(shape.clone()), transform, material, None),
         aabb(shape, &transform),
     )
 }
 
+pub fn create_bvt_tuple_blockage<G>(
+    shape: &G,
+    transform: Isometry<f32>,
+    blockage: Blockage,
+) -> (SceneObject, AABB<f32>)
+where
+    G: 'static + Clone + SceneGeometry + HasBoundingVolume<f32, AABB<f32>>,
+{
+    let mut object = SceneObject::new(
+        Box::new(shape.clone()),
+        transform,
+        Material::dielectric(1.),
+        None,
+    );
+    object.blockage = Some(blockage);
+    (object, aabb(shape, &transform))
+}
+
 pub fn create_bvt_tuple_receiver<G>(
     shape: &G,
     transform: Isometry<f32>,
     receiver: usize,
 ) -> (SceneObject, AABB<f32>)
 where
-    G: 'static + Send + Sync + Clone + RayCast<f32> + HasBoundingVolume<f32, AABB<f32>>,
+    G: 'static + Clone + SceneGeometry + HasBoundingVolume<f32, AABB<f32>>,
 {
     (
-        SceneObject::new(Box::new(shape.clone()), transform, 1., Some(receiver)),
+        SceneObject::new(
+            Box::new(shape.clone()),
+            transform,
+            Material::dielectric(1.),
+            Some(receiver),
+        ),
         aabb(shape, &transform),
     )
 }
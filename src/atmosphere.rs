@@ -0,0 +1,237 @@
+// atmosphere.rs
+//! Specific attenuation of the air between obstacles.
+//! Gaseous absorption follows the simplified model of ITU-R P.676 (Annex 2,
+//! valid from 1 to 350 GHz) and rain follows ITU-R P.838-3.
+
+/// Conditions of the air, standard atmosphere by default
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Atmosphere {
+    pub temperature: f32, // °C
+    pub humidity: f32,    // relative, in %
+    pub pressure: f32,    // hPa
+    pub rain_rate: f32,   // mm/h
+}
+
+impl Default for Atmosphere {
+    fn default() -> Self {
+        Self {
+            temperature: 15.0,
+            humidity: 58.0, // ~7.5 g/m³ at 15 °C
+            pressure: 1013.25,
+            rain_rate: 0.0,
+        }
+    }
+}
+
+impl Atmosphere {
+    /// Water vapour density in g/m³
+    pub fn vapour_density(&self) -> f32 {
+        let t = self.temperature;
+        // Saturation pressure in hPa (ITU-R P.453)
+        let saturation = 6.1121 * (17.502 * t / (t + 240.97)).exp();
+        self.humidity / 100.0 * saturation * 216.7 / (t + 273.15)
+    }
+
+    /// Total specific attenuation in dB/km at `frequency` Hz
+    pub fn attenuation_db(&self, frequency: f32) -> f32 {
+        let f = frequency / 1e9;
+        self.oxygen(f) + self.water_vapour(f) + self.rain(f)
+    }
+
+    /// Power attenuation coefficient per meter, to be used as `exp(-absorbance * d)`
+    pub fn absorbance(&self, frequency: f32) -> f32 {
+        self.attenuation_db(frequency) * std::f32::consts::LN_10 / 10.0 / 1000.0
+    }
+
+    fn ratios(&self) -> (f32, f32) {
+        (self.pressure / 1013.0, 288.0 / (273.0 + self.temperature))
+    }
+
+    /// Dry air attenuation in dB/km, f in GHz
+    fn oxygen(&self, f: f32) -> f32 {
+        let (rp, rt) = self.ratios();
+        let phi = |a: f32, b: f32, c: f32, d: f32| {
+            rp.powf(a) * rt.powf(b) * (c * (1.0 - rp) + d * (1.0 - rt)).exp()
+        };
+
+        let g54 = 2.192 * phi(1.8286, -1.9487, 0.4051, -2.8509);
+        let g58 = 12.59 * phi(1.0045, 3.5610, 0.1588, 1.2834);
+        let g60 = 15.0 * phi(0.9003, 4.1335, 0.0427, 1.6088);
+        let g62 = 14.28 * phi(0.9886, 3.4176, 0.1827, 1.3429);
+        let g64 = 6.819 * phi(1.4320, 0.6258, 0.3177, -0.5914);
+        let g66 = 1.908 * phi(2.0717, -4.1404, 0.4910, -4.8718);
+
+        if f <= 54.0 {
+            let x1 = phi(0.0717, -1.8132, 0.0156, -1.6515);
+            let x2 = phi(0.5146, -4.6368, -0.1921, -5.7416);
+            let x3 = phi(0.3414, -6.5851, 0.2130, -8.5854);
+            (7.2 * rt.powf(2.8) / (f * f + 0.34 * rp * rp * rt.powf(1.6))
+                + 0.62 * x3 / ((54.0 - f).powf(1.16 * x1) + 0.83 * x2))
+                * f
+                * f
+                * rp
+                * rp
+                * 1e-3
+        } else if f <= 60.0 {
+            // Interpolation between the lines of the 60 GHz complex
+            (g54.ln() / 24.0 * (f - 58.0) * (f - 60.0) - g58.ln() / 8.0 * (f - 54.0) * (f - 60.0)
+                + g60.ln() / 12.0 * (f - 54.0) * (f - 58.0))
+                .exp()
+        } else if f <= 62.0 {
+            g60 + (g62 - g60) * (f - 60.0) / 2.0
+        } else if f <= 66.0 {
+            (g62.ln() / 8.0 * (f - 64.0) * (f - 66.0) - g64.ln() / 4.0 * (f - 62.0) * (f - 66.0)
+                + g66.ln() / 8.0 * (f - 62.0) * (f - 64.0))
+                .exp()
+        } else if f <= 120.0 {
+            let x4 = phi(-0.0112, 0.0092, -0.1033, -0.0009);
+            let x5 = phi(0.2705, -2.7192, -0.3016, -4.1033);
+            let x6 = phi(0.2445, -5.9191, 0.0422, -8.0719);
+            let x7 = phi(-0.1833, 6.5589, -0.2402, 6.131);
+            (3.02e-4 * rt.powf(3.5)
+                + 0.283 * rt.powf(3.8) / ((f - 118.75).powi(2) + 2.91 * rp * rp * rt.powf(1.6))
+                + 0.502 * x6 * (1.0 - 0.0163 * x7 * (f - 66.0))
+                    / ((f - 66.0).powf(1.4346 * x4) + 1.15 * x5))
+                * f
+                * f
+                * rp
+                * rp
+                * 1e-3
+        } else {
+            let delta = -0.00306 * phi(3.211, -14.94, 1.583, -16.37);
+            (3.02e-4 / (1.0 + 1.9e-5 * f.powf(1.5))
+                + 0.283 * rt.powf(0.3) / ((f - 118.75).powi(2) + 2.91 * rp * rp * rt.powf(1.6)))
+                * f
+                * f
+                * rp
+                * rp
+                * rt.powf(3.5)
+                * 1e-3
+                + delta
+        }
+    }
+
+    /// Water vapour attenuation in dB/km, f in GHz
+    fn water_vapour(&self, f: f32) -> f32 {
+        let (rp, rt) = self.ratios();
+        let rho = self.vapour_density();
+        let e1 = 0.955 * rp * rt.powf(0.68) + 0.006 * rho;
+        let e2 = 0.735 * rp * rt.powf(0.5) + 0.0353 * rt.powi(4) * rho;
+        let g = |fi: f32| 1.0 + ((f - fi) / (f + fi)).powi(2);
+        // (strength, line, temperature exponent, width)
+        let line = |a: f32, fi: f32, b: f32, w: f32| {
+            a * e1 * (b * (1.0 - rt)).exp() / ((f - fi).powi(2) + w * e1 * e1)
+        };
+
+        let sum = line(3.98, 22.235, 2.23, 9.42) * g(22.0)
+            + line(11.96, 183.31, 0.7, 11.14)
+            + line(0.081, 321.226, 6.44, 6.29)
+            + line(3.66, 325.153, 1.6, 9.22)
+            + line(25.37, 380.0, 1.09, 0.0)
+            + line(17.4, 448.0, 1.46, 0.0)
+            + line(844.6, 557.0, 0.17, 0.0) * g(557.0)
+            + line(290.0, 752.0, 0.41, 0.0) * g(752.0)
+            + 8.3328e4 * e2 * (0.99 * (1.0 - rt)).exp() / (f - 1780.0).powi(2) * g(1780.0);
+
+        sum * f * f * rt.powf(2.5) * rho * 1e-4
+    }
+
+    /// Rain attenuation in dB/km for a circular polarisation, f in GHz
+    fn rain(&self, f: f32) -> f32 {
+        if self.rain_rate <= 0.0 {
+            return 0.0;
+        }
+
+        let lf = f.log10();
+        let fit = |a: &[f32], b: &[f32], c: &[f32], m: f32, k: f32| {
+            a.iter()
+                .zip(b)
+                .zip(c)
+                .map(|((a, b), c)| a * (-((lf - b) / c).powi(2)).exp())
+                .sum::<f32>()
+                + m * lf
+                + k
+        };
+
+        let kh = 10f32.powf(fit(
+            &[-5.33980, -0.35351, -0.23789, -0.94158],
+            &[-0.10008, 1.26970, 0.86036, 0.64552],
+            &[1.13098, 0.45400, 0.15354, 0.16817],
+            -0.18961,
+            0.71147,
+        ));
+        let kv = 10f32.powf(fit(
+            &[-3.80595, -3.44965, -0.39902, 0.50167],
+            &[0.56934, -0.22911, 0.73042, 1.07319],
+            &[0.81061, 0.51059, 0.11899, 0.27195],
+            -0.16398,
+            0.63297,
+        ));
+        let ah = fit(
+            &[-0.14318, 0.29591, 0.32177, -5.37610, 16.1721],
+            &[1.82442, 0.77564, 0.63773, -0.96230, -3.29980],
+            &[-0.55187, 0.19822, 0.13164, 1.47828, 3.43990],
+            0.67849,
+            -1.95537,
+        );
+        let av = fit(
+            &[-0.07771, 0.56727, -0.20238, -48.2991, 48.5833],
+            &[2.33840, 0.95545, 1.14520, 0.791669, 0.791459],
+            &[-0.76284, 0.54039, 0.26809, 0.116226, 0.116479],
+            -0.053739,
+            0.83433,
+        );
+
+        // Horizontal path, 45° polarisation tilt
+        let k = (kh + kv) / 2.0;
+        let alpha = (kh * ah + kv * av) / (2.0 * k);
+        k * self.rain_rate.powf(alpha)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32, tolerance: f32) -> bool {
+        (a - b).abs() <= tolerance * b.abs()
+    }
+
+    #[test]
+    fn standard_atmosphere() {
+        let air = Atmosphere::default();
+        assert!(close(air.vapour_density(), 7.5, 0.02));
+        // Peak of the 60 GHz oxygen complex at sea level
+        assert!(close(air.oxygen(60.0), 15.0, 0.02));
+        // Water vapour line at 22 GHz, about 0.2 dB/km for 7.5 g/m³
+        assert!(close(air.water_vapour(22.235), 0.2, 0.15));
+        assert!(air.attenuation_db(1e9) < 0.01);
+    }
+
+    #[test]
+    fn rain() {
+        let rain = Atmosphere {
+            rain_rate: 10.0,
+            ..Atmosphere::default()
+        };
+        // P.838-3 table 5 at 10 GHz: kH 0.01217, aH 1.2571, kV 0.01129, aV 1.2156
+        let (kh, ah, kv, av) = (0.01217f32, 1.2571f32, 0.01129f32, 1.2156f32);
+        let k = (kh + kv) / 2.0;
+        let alpha = (kh * ah + kv * av) / (2.0 * k);
+        assert!(close(rain.rain(10.0), k * 10f32.powf(alpha), 0.02));
+        assert_eq!(Atmosphere::default().rain(10.0), 0.0);
+    }
+
+    #[test]
+    fn absorbance() {
+        let rain = Atmosphere {
+            rain_rate: 25.0,
+            ..Atmosphere::default()
+        };
+        // A kilometre loses the attenuation in dB
+        let f = 28e9;
+        let db = -10.0 * (-rain.absorbance(f) * 1000.0).exp().log10();
+        assert!(close(db, rain.attenuation_db(f), 1e-4));
+    }
+}
@@ -0,0 +1,86 @@
+// blockage.rs
+//! Obstacles that are not simple dielectrics. Rays go straight through them
+//! and lose energy according to the length of the segment inside the volume.
+
+/// Effective loss through a human body in dB/m. Tissues alone would absorb
+/// everything at 60 GHz, but the energy diffracted around the body is not traced.
+pub const BODY_LOSS: f32 = 100.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum FoliageModel {
+    Weissberger,
+    /// Early ITU-R model (CCIR report 236-2)
+    Itu,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Blockage {
+    Foliage(FoliageModel),
+    /// Lossy cylinder, loss in dB/m
+    Body(f32),
+}
+
+impl Blockage {
+    /// Excess loss in dB for a segment of `depth` meters, `frequency` in Hz
+    pub fn loss_db(&self, depth: f32, frequency: f32) -> f32 {
+        match *self {
+            Blockage::Foliage(FoliageModel::Weissberger) => {
+                let f = frequency / 1e9;
+                if depth <= 14.0 {
+                    0.45 * f.powf(0.284) * depth
+                } else {
+                    1.33 * f.powf(0.284) * depth.min(400.0).powf(0.588)
+                }
+            }
+            Blockage::Foliage(FoliageModel::Itu) => {
+                let f = frequency / 1e6;
+                0.2 * f.powf(0.3) * depth.min(400.0).powf(0.6)
+            }
+            Blockage::Body(loss) => loss * depth,
+        }
+    }
+
+    /// Factor to apply to the energy of a ray crossing `depth` meters of the volume
+    pub fn gain(&self, depth: f32, frequency: f32) -> f32 {
+        10f32.powf(-self.loss_db(depth, frequency) / 10.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() <= 1e-3 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn weissberger() {
+        let model = Blockage::Foliage(FoliageModel::Weissberger);
+        // At 1 GHz the frequency term is 1
+        assert!(close(model.loss_db(10.0, 1e9), 4.5));
+        assert!(close(model.loss_db(14.0, 1e9), 6.3));
+        // Both branches nearly meet at 14 m
+        assert!((model.loss_db(14.001, 1e9) - 6.3).abs() < 0.05);
+        assert!(close(model.loss_db(100.0, 1e9), 1.33 * 100f32.powf(0.588)));
+        // No more loss past 400 m
+        assert!(close(model.loss_db(400.0, 1e9), 1.33 * 400f32.powf(0.588)));
+        assert_eq!(model.loss_db(1000.0, 1e9), model.loss_db(400.0, 1e9));
+        assert!(close(model.loss_db(10.0, 60e9), 4.5 * 60f32.powf(0.284)));
+    }
+
+    #[test]
+    fn itu() {
+        let model = Blockage::Foliage(FoliageModel::Itu);
+        assert!(close(model.loss_db(10.0, 1e9), 0.2 * 1000f32.powf(0.3) * 10f32.powf(0.6)));
+        assert_eq!(model.loss_db(1000.0, 1e9), model.loss_db(400.0, 1e9));
+    }
+
+    #[test]
+    fn body() {
+        let model = Blockage::Body(BODY_LOSS);
+        assert!(close(model.loss_db(0.3, 60e9), 30.0));
+        assert!(close(model.gain(0.3, 60e9), 1e-3));
+        assert_eq!(model.gain(0.0, 60e9), 1.0);
+    }
+}
@@ -0,0 +1,273 @@
+// channels.rs
+//! Synthetic reference channels, to test the OFDM chain without tracing a scene.
+//!
+//! As for traced transfer functions, the gain of a tap is the power it carries.
+//! Taps are real, so the phase of the fading is reduced to the sign of its
+//! real part.
+
+use crate::antennas::{SignalEvent, WorldDescriptor};
+use crate::TIME_PER_BEAT;
+
+use rand::distributions::StandardNormal;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use std::collections::BTreeMap;
+
+/// 3GPP TR 38.901 table 7.7.2-1, normalized delay and power in dB, Rayleigh taps
+const TDL_A: [(f32, f32); 23] = [
+    (0.0000, -13.4),
+    (0.3819, 0.0),
+    (0.4025, -2.2),
+    (0.5868, -4.0),
+    (0.4610, -6.0),
+    (0.5375, -8.2),
+    (0.6708, -9.9),
+    (0.5750, -10.5),
+    (0.7618, -7.5),
+    (1.5375, -15.9),
+    (1.8978, -6.6),
+    (2.2242, -16.7),
+    (2.1718, -12.4),
+    (2.4942, -15.2),
+    (2.5119, -10.8),
+    (3.0582, -11.3),
+    (4.0810, -12.7),
+    (4.4579, -16.2),
+    (4.5695, -18.3),
+    (4.7966, -18.9),
+    (5.0066, -16.6),
+    (5.3043, -19.9),
+    (9.6586, -29.7),
+];
+
+/// Table 7.7.2-2
+const TDL_B: [(f32, f32); 23] = [
+    (0.0000, 0.0),
+    (0.1072, -2.2),
+    (0.2155, -4.0),
+    (0.2095, -3.2),
+    (0.2870, -9.8),
+    (0.2986, -1.2),
+    (0.3752, -3.4),
+    (0.5055, -5.2),
+    (0.3681, -7.6),
+    (0.3697, -3.0),
+    (0.5700, -8.9),
+    (0.5283, -9.0),
+    (1.1021, -4.8),
+    (1.2756, -5.7),
+    (1.5474, -7.5),
+    (1.7842, -1.9),
+    (2.0169, -7.6),
+    (2.8294, -12.2),
+    (3.0219, -9.8),
+    (3.6187, -11.4),
+    (4.1067, -14.9),
+    (4.2790, -9.2),
+    (4.7834, -11.3),
+];
+
+/// Table 7.7.2-3
+// 0.6366 is a delay of the table, not 2 / pi
+#[allow(clippy::approx_constant)]
+const TDL_C: [(f32, f32); 24] = [
+    (0.0000, -4.4),
+    (0.2099, -1.2),
+    (0.2219, -3.5),
+    (0.2329, -5.2),
+    (0.2176, -2.5),
+    (0.6366, 0.0),
+    (0.6448, -2.2),
+    (0.6560, -3.9),
+    (0.6584, -7.4),
+    (0.7935, -7.1),
+    (0.8213, -10.7),
+    (0.9336, -11.1),
+    (1.2285, -5.1),
+    (1.3083, -6.8),
+    (2.1704, -8.7),
+    (2.7105, -13.2),
+    (4.2589, -13.9),
+    (4.6003, -13.9),
+    (5.4902, -15.8),
+    (5.6077, -17.1),
+    (6.3065, -16.0),
+    (6.6374, -15.7),
+    (7.0427, -21.6),
+    (8.6523, -22.8),
+];
+
+/// Delays of the exponential profiles, in delay spreads: up to 30 dB below the first tap
+const EXPONENTIAL_SPAN: f32 = 6.9;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ChannelModel {
+    TdlA,
+    TdlB,
+    TdlC,
+    /// Exponential power-delay profile of that many taps, without fading
+    Exponential(usize),
+    /// Exponential profile of that many taps, each with Rayleigh fading
+    Rayleigh(usize),
+    /// Rayleigh profile whose first tap has a line of sight, `k_factor` in dB
+    /// being the one of that tap
+    Rician { taps: usize, k_factor: f32 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SyntheticChannel {
+    pub model: ChannelModel,
+    /// RMS delay spread, in seconds. The normalized delays of the TDL models
+    /// are scaled by it, as in TR 38.901.
+    pub delay_spread: f32,
+    pub seed: u64,
+    /// Mean received power over emitted power, in dB
+    #[serde(default)]
+    pub path_loss: f32,
+}
+
+impl SyntheticChannel {
+    /// Normalized delay and mean power of each tap
+    fn profile(&self) -> Vec<(f32, f32)> {
+        let exponential = |taps: usize| -> Vec<(f32, f32)> {
+            (0..taps)
+                .map(|k| {
+                    let delay = k as f32 * EXPONENTIAL_SPAN / taps as f32;
+                    (delay, (-delay).exp())
+                })
+                .collect()
+        };
+        let table = |x: &[(f32, f32)]| -> Vec<(f32, f32)> {
+            x.iter()
+                .map(|&(delay, db)| (delay, 10f32.powf(db / 10.0)))
+                .collect()
+        };
+        let mut profile = match self.model {
+            ChannelModel::TdlA => table(&TDL_A),
+            ChannelModel::TdlB => table(&TDL_B),
+            ChannelModel::TdlC => table(&TDL_C),
+            ChannelModel::Exponential(taps)
+            | ChannelModel::Rayleigh(taps)
+            | ChannelModel::Rician { taps, .. } => exponential(taps),
+        };
+        let total: f32 = profile.iter().map(|x| x.1).sum();
+        for tap in &mut profile {
+            tap.1 /= total;
+        }
+        profile
+    }
+
+    /// Transfer function sorted by time for an emitter of power `max_power`,
+    /// the random draws following `seed` and `link`
+    pub fn transfer(&self, max_power: f32, link: u64) -> Vec<SignalEvent> {
+        let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(link));
+        let gain = max_power * 10f32.powf(-self.path_loss / 10.0);
+        // Complex gaussian of unit power
+        let gaussian = |rng: &mut StdRng| {
+            let scale = std::f64::consts::FRAC_1_SQRT_2;
+            (
+                (rng.sample(StandardNormal) * scale) as f32,
+                (rng.sample(StandardNormal) * scale) as f32,
+            )
+        };
+
+        // Taps landing on the same beat add up
+        let mut taps = BTreeMap::new();
+        for (i, (delay, power)) in self.profile().into_iter().enumerate() {
+            let (re, im) = match self.model {
+                ChannelModel::Exponential(_) => (1.0, 0.0),
+                ChannelModel::Rician { k_factor, .. } if i == 0 => {
+                    let k = 10f32.powf(k_factor / 10.0);
+                    let (re, im) = gaussian(&mut rng);
+                    let los = (k / (k + 1.0)).sqrt();
+                    let nlos = (1.0 / (k + 1.0)).sqrt();
+                    (los + nlos * re, nlos * im)
+                }
+                _ => gaussian(&mut rng),
+            };
+            let fading = if re < 0.0 { -(re * re + im * im) } else { re * re + im * im };
+            let time = (delay * self.delay_spread / TIME_PER_BEAT).round() as usize;
+            *taps.entry(time).or_insert(0.0) += gain * power * fading;
+        }
+        taps.into_iter()
+            .map(|(time, gain)| SignalEvent { time, gain })
+            .collect()
+    }
+}
+
+/// Replace the transfer functions of every receiver by synthetic ones, each
+/// link drawing its own fading. The channel does not move, the frames of a
+/// timeline are removed.
+pub fn apply(world: &mut WorldDescriptor, channel: &SyntheticChannel) {
+    let emitters = &world.emitters;
+    for (r, receiver) in world.receivers.iter_mut().enumerate() {
+        if let Some(receiver) = receiver.as_mut() {
+            receiver.frames.clear();
+            receiver.transfers = emitters
+                .iter()
+                .enumerate()
+                .map(|(e, emitter)| match emitter {
+                    Some(x) if e != r => {
+                        channel.transfer(x.max_power, (r * emitters.len() + e) as u64)
+                    }
+                    _ => Vec::new(),
+                })
+                .collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(model: ChannelModel) -> SyntheticChannel {
+        SyntheticChannel {
+            model,
+            delay_spread: 100e-9,
+            seed: 1,
+            path_loss: 0.0,
+        }
+    }
+
+    #[test]
+    fn profiles_are_normalized() {
+        let models = vec![
+            ChannelModel::TdlA,
+            ChannelModel::TdlB,
+            ChannelModel::TdlC,
+            ChannelModel::Exponential(8),
+            ChannelModel::Rayleigh(1),
+            ChannelModel::Rician {
+                taps: 4,
+                k_factor: 10.0,
+            },
+        ];
+        for model in models {
+            let profile = channel(model.clone()).profile();
+            let total: f32 = profile.iter().map(|x| x.1).sum();
+            assert!((total - 1.0).abs() < 1e-5, "{:?} sums to {}", model, total);
+            assert!(profile.iter().all(|x| x.1 > 0.0 && x.0 >= 0.0));
+        }
+    }
+
+    #[test]
+    fn exponential_transfer() {
+        let taps = channel(ChannelModel::Exponential(4)).transfer(2.0, 0);
+        let total: f32 = taps.iter().map(|x| x.gain).sum();
+        assert!((total - 2.0).abs() < 1e-5);
+        assert!(taps.windows(2).all(|x| x[0].time < x[1].time));
+        assert!(taps.windows(2).all(|x| x[0].gain > x[1].gain));
+    }
+
+    #[test]
+    fn fading_follows_the_seed() {
+        let rayleigh = channel(ChannelModel::Rayleigh(8));
+        let gains = |link| -> Vec<f32> {
+            rayleigh.transfer(1.0, link).iter().map(|x| x.gain).collect()
+        };
+        assert_eq!(gains(3), gains(3));
+        assert_ne!(gains(3), gains(4));
+    }
+}
@@ -0,0 +1,175 @@
+// city.rs
+//! Seeded generator of urban scenes: a grid of blocks separated by streets,
+//! each block lined with sidewalks and split into building lots.
+
+use crate::antennas::{create_bvt_tuple, SceneObject};
+use crate::materials::{Material, MaterialLibrary};
+use crate::scene::{MaterialDescription, SceneError};
+
+use nalgebra::{Isometry3, Translation3, Unit, Vector3};
+use ncollide3d::bounding_volume::aabb::AABB;
+use ncollide3d::shape::{Cuboid, Plane};
+use rand::distributions::{Distribution, LogNormal};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CityParameters {
+    pub seed: u64,
+    /// Number of blocks along x and z
+    pub blocks: [usize; 2],
+    pub block_size: f32,
+    pub street_width: f32,
+    pub sidewalk_width: f32,
+    pub sidewalk_height: f32,
+    /// Lots along each side of a block
+    pub lots: usize,
+    /// Probability for a lot to be built
+    pub density: f32,
+    /// Heights follow a log-normal law
+    pub median_height: f32,
+    pub height_sigma: f32,
+    pub max_height: f32,
+    pub building_material: MaterialDescription,
+    pub sidewalk_material: MaterialDescription,
+    pub ground_material: MaterialDescription,
+}
+
+impl Default for CityParameters {
+    fn default() -> Self {
+        let material = |name: &str| MaterialDescription::Named(name.to_string());
+        Self {
+            seed: 0,
+            blocks: [4, 4],
+            block_size: 80.0,
+            street_width: 20.0,
+            sidewalk_width: 3.0,
+            sidewalk_height: 0.15,
+            lots: 3,
+            density: 0.8,
+            median_height: 15.0,
+            height_sigma: 0.5,
+            max_height: 120.0,
+            building_material: material("concrete"),
+            sidewalk_material: material("concrete"),
+            ground_material: material("soil"),
+        }
+    }
+}
+
+type Objects = Vec<(SceneObject, AABB<f32>)>;
+
+/// Cuboid from its lower corner and its size
+fn block(
+    transform: &Isometry3<f32>,
+    corner: Vector3<f32>,
+    size: Vector3<f32>,
+    material: &Material,
+) -> (SceneObject, AABB<f32>) {
+    let local = Isometry3::from_parts(
+        Translation3::from(corner + size / 2.0),
+        nalgebra::UnitQuaternion::identity(),
+    );
+    create_bvt_tuple(&Cuboid::new(size / 2.0), transform * local, material.clone())
+}
+
+impl CityParameters {
+    fn check(&self, entry: &str) -> Result<(), SceneError> {
+        let positive = [
+            self.block_size,
+            self.street_width,
+            self.median_height,
+            self.max_height,
+        ];
+        if positive.iter().any(|&x| x <= 0.0)
+            || self.sidewalk_width < 0.0
+            || self.sidewalk_height < 0.0
+            || self.height_sigma < 0.0
+            || self.lots == 0
+            || self.blocks.contains(&0)
+        {
+            return Err(SceneError::new(entry, "city dimensions must be positive"));
+        }
+        if 2.0 * self.sidewalk_width >= self.block_size {
+            return Err(SceneError::new(entry, "sidewalks are wider than the blocks"));
+        }
+        if self.density < 0.0 || self.density > 1.0 {
+            return Err(SceneError::new(entry, "density must be between 0 and 1"));
+        }
+        Ok(())
+    }
+
+    /// Ground, sidewalks and buildings, centered on the origin of `transform`
+    pub fn generate(
+        &self,
+        entry: &str,
+        transform: &Isometry3<f32>,
+        library: &MaterialLibrary,
+    ) -> Result<Objects, SceneError> {
+        self.check(entry)?;
+        let building_material = self.building_material.resolve(entry, library)?;
+        let sidewalk_material = self.sidewalk_material.resolve(entry, library)?;
+
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let heights = LogNormal::new(
+            f64::from(self.median_height).ln(),
+            f64::from(self.height_sigma),
+        );
+
+        let ground = create_bvt_tuple(
+            &Plane::new(Unit::new_normalize(Vector3::y())),
+            *transform,
+            self.ground_material.resolve(entry, library)?,
+        );
+        let mut res = vec![ground];
+
+        let pitch = self.block_size + self.street_width;
+        let offset = |count: usize| -(count as f32 * pitch - self.street_width) / 2.0;
+        let lot = (self.block_size - 2.0 * self.sidewalk_width) / self.lots as f32;
+
+        for i in 0..self.blocks[0] {
+            for j in 0..self.blocks[1] {
+                let x = offset(self.blocks[0]) + i as f32 * pitch;
+                let z = offset(self.blocks[1]) + j as f32 * pitch;
+
+                if self.sidewalk_height > 0.0 {
+                    res.push(block(
+                        transform,
+                        Vector3::new(x, 0.0, z),
+                        Vector3::new(self.block_size, self.sidewalk_height, self.block_size),
+                        &sidewalk_material,
+                    ));
+                }
+
+                for u in 0..self.lots {
+                    for v in 0..self.lots {
+                        if rng.gen::<f32>() >= self.density {
+                            continue;
+                        }
+
+                        // Buildings fill 70 to 100% of their lot, outer ones on the sidewalk
+                        let width = lot * rng.gen_range(0.7, 1.0);
+                        let depth = lot * rng.gen_range(0.7, 1.0);
+                        let along_x = if u == 0 { 0.0 } else { lot - width };
+                        let along_z = if v == 0 { 0.0 } else { lot - depth };
+                        let height = (heights.sample(&mut rng) as f32).max(3.0).min(self.max_height);
+
+                        res.push(block(
+                            transform,
+                            Vector3::new(
+                                x + self.sidewalk_width + u as f32 * lot + along_x,
+                                self.sidewalk_height,
+                                z + self.sidewalk_width + v as f32 * lot + along_z,
+                            ),
+                            Vector3::new(width, height, depth),
+                            &building_material,
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(res)
+    }
+}
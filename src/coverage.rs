@@ -0,0 +1,276 @@
+// coverage.rs
+use crate::antennas::{SceneObject, SignalEmitter, SignalEvent, SignalReceiver, WorldDescriptor};
+use crate::simulation::ReceptionKind;
+use crate::waves::{tracing, TracerConfig};
+use crate::TIME_PER_BEAT;
+
+use nalgebra::Point3;
+use ncollide3d::bounding_volume::aabb::AABB;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+const PI: f32 = std::f32::consts::PI;
+/// Dynamic range of the exported images, in dB below the strongest cell
+const IMAGE_DYNAMIC_RANGE: f32 = 60.0;
+
+/// Regular grid of virtual receivers. A 2D map is a grid with `dims[1] == 1`.
+#[derive(Clone, Debug)]
+pub struct CoverageGrid {
+    pub origin: Point3<f32>, // Center of the first cell
+    pub step: f32,
+    pub dims: [usize; 3],
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CoverageCell {
+    pub power: f32,        // Received power per square meter
+    pub mean_delay: f32,   // seconds
+    pub delay_spread: f32, // RMS, seconds
+}
+
+pub struct CoverageMap {
+    pub grid: CoverageGrid,
+    pub cells: Vec<CoverageCell>, // x first, then z, then y
+}
+
+impl CoverageGrid {
+    pub fn len(&self) -> usize {
+        self.dims[0] * self.dims[1] * self.dims[2]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        x + self.dims[0] * (z + self.dims[2] * y)
+    }
+
+    pub fn position(&self, index: usize) -> Point3<f32> {
+        let x = index % self.dims[0];
+        let z = (index / self.dims[0]) % self.dims[2];
+        let y = index / (self.dims[0] * self.dims[2]);
+        self.origin + nalgebra::Vector3::new(x as f32, y as f32, z as f32) * self.step
+    }
+}
+
+/// Trace the scene once with a receiver in every cell of the grid
+pub fn coverage(
+    grid: CoverageGrid,
+    emitters: Vec<SignalEmitter>,
+    collisions: Vec<(SceneObject, AABB<f32>)>,
+    config: &TracerConfig,
+) -> CoverageMap {
+    let nb_emitters = emitters.len();
+    let count = nb_emitters + grid.len();
+
+    let mut world = WorldDescriptor {
+        emitters: Vec::with_capacity(count),
+        receivers: Vec::with_capacity(count),
+        names: Vec::with_capacity(count),
+        collisions,
+    };
+
+    for (i, emitter) in emitters.into_iter().enumerate() {
+        world.emitters.push(Some(emitter));
+        world.receivers.push(None);
+        world.names.push(format!("emitter_{}", i));
+    }
+
+    for i in 0..grid.len() {
+        world.emitters.push(None);
+        world.receivers.push(Some(SignalReceiver {
+            position: grid.position(i),
+            // Only the emitters' slots are ever filled by the tracer
+            transfers: vec![Vec::new(); nb_emitters],
+            paths: Vec::new(),
+            frames: Vec::new(),
+            kind: ReceptionKind::None,
+        }));
+        world.names.push(format!("cell_{}", i));
+    }
+
+    // Cells must not overlap or rays would be counted twice
+    let config = TracerConfig {
+        receiver_radius: config.receiver_radius.min(grid.step * 0.5),
+        ..config.clone()
+    };
+    tracing(&mut world, &config);
+
+    let area = PI * config.receiver_radius.powi(2);
+    let cells = world
+        .receivers
+        .iter()
+        .skip(nb_emitters)
+        .map(|rec| {
+            let rec = rec.as_ref().expect("Unreachable: coverage cell without receiver");
+            let mut cell = delay_statistics(rec.transfers.iter().flatten());
+            cell.power /= area;
+            cell
+        })
+        .collect();
+
+    CoverageMap { grid, cells }
+}
+
+fn delay_statistics<'a>(events: impl Iterator<Item = &'a SignalEvent> + Clone) -> CoverageCell {
+    let power: f32 = events.clone().map(|e| e.gain.abs()).sum();
+    if power == 0.0 {
+        return CoverageCell::default();
+    }
+
+    let delay = |e: &SignalEvent| e.time as f32 * TIME_PER_BEAT;
+    let mean = events.clone().map(|e| e.gain.abs() * delay(e)).sum::<f32>() / power;
+    let square = events.map(|e| e.gain.abs() * delay(e).powi(2)).sum::<f32>() / power;
+
+    CoverageCell {
+        power,
+        mean_delay: mean,
+        delay_spread: (square - mean * mean).max(0.0).sqrt(),
+    }
+}
+
+impl CoverageMap {
+    pub fn write_csv(&self, path: &str) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "x,y,z,power,power_db,mean_delay,delay_spread")?;
+        for (i, cell) in self.cells.iter().enumerate() {
+            let pos = self.grid.position(i);
+            writeln!(
+                file,
+                "{},{},{},{},{},{},{}",
+                pos.x,
+                pos.y,
+                pos.z,
+                cell.power,
+                10.0 * cell.power.log10(),
+                cell.mean_delay,
+                cell.delay_spread
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Horizontal slice `layer` as a grayscale image, x to the right and z downwards
+    pub fn write_pgm(&self, path: &str, layer: usize) -> io::Result<()> {
+        let levels = self.levels(layer);
+        let mut file = BufWriter::new(File::create(path)?);
+        write!(file, "P5\n{} {}\n255\n", self.grid.dims[0], self.grid.dims[2])?;
+        let pixels: Vec<u8> = levels.iter().map(|&l| (l * 255.0) as u8).collect();
+        file.write_all(&pixels)
+    }
+
+    /// Same as `write_pgm` with a blue to red colormap
+    pub fn write_ppm(&self, path: &str, layer: usize) -> io::Result<()> {
+        let levels = self.levels(layer);
+        let mut file = BufWriter::new(File::create(path)?);
+        write!(file, "P6\n{} {}\n255\n", self.grid.dims[0], self.grid.dims[2])?;
+        let pixels: Vec<u8> = levels.iter().flat_map(|&l| colormap(l).to_vec()).collect();
+        file.write_all(&pixels)
+    }
+
+    /// Power of the cells of a layer in dB, mapped to [0, 1]
+    fn levels(&self, layer: usize) -> Vec<f32> {
+        assert!(layer < self.grid.dims[1], "Coverage layer out of bounds");
+
+        let start = self.grid.index(0, layer, 0);
+        let end = start + self.grid.dims[0] * self.grid.dims[2];
+        let db: Vec<f32> = self.cells[start..end]
+            .iter()
+            .map(|c| 10.0 * c.power.log10())
+            .collect();
+
+        let max = db.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        let min = max - IMAGE_DYNAMIC_RANGE;
+        db.iter()
+            .map(|&x| {
+                if x.is_finite() {
+                    ((x - min) / IMAGE_DYNAMIC_RANGE).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+}
+
+fn colormap(level: f32) -> [u8; 3] {
+    // Piecewise linear "jet": blue, cyan, yellow, red
+    let channel = |center: f32| ((1.5 - (4.0 * level - center).abs()).clamp(0.0, 1.0) * 255.0) as u8;
+    if level == 0.0 {
+        [0, 0, 0]
+    } else {
+        [channel(3.0), channel(2.0), channel(1.0)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> CoverageGrid {
+        CoverageGrid {
+            origin: Point3::new(1.0, 2.0, 3.0),
+            step: 0.5,
+            dims: [2, 2, 3],
+        }
+    }
+
+    #[test]
+    fn indexing() {
+        let grid = grid();
+        assert_eq!(grid.len(), 12);
+        let mut seen = vec![false; grid.len()];
+        for y in 0..2 {
+            for z in 0..3 {
+                for x in 0..2 {
+                    let i = grid.index(x, y, z);
+                    assert!(!seen[i]);
+                    seen[i] = true;
+                    let offset = nalgebra::Vector3::new(x as f32, y as f32, z as f32) * 0.5;
+                    let expected = Point3::new(1.0, 2.0, 3.0) + offset;
+                    assert!((grid.position(i) - expected).norm() < 1e-6);
+                }
+            }
+        }
+        // x first, then z, then y
+        assert_eq!(grid.index(1, 0, 0), 1);
+        assert_eq!(grid.index(0, 0, 1), 2);
+        assert_eq!(grid.index(0, 1, 0), 6);
+    }
+
+    #[test]
+    fn levels() {
+        let grid = grid();
+        let mut cells = vec![CoverageCell { power: 1.0, ..CoverageCell::default() }; grid.len()];
+        cells[grid.index(1, 0, 0)].power = 1e-7; // Below the dynamic range
+        cells[grid.index(0, 0, 2)].power = 0.0; // Not reached at all
+        cells[grid.index(0, 1, 0)].power = 10.0;
+        cells[grid.index(1, 1, 2)].power = 1e-3;
+        let map = CoverageMap { grid, cells };
+
+        // Each layer is scaled to its own strongest cell
+        assert_eq!(map.levels(0), [1.0, 0.0, 1.0, 1.0, 0.0, 1.0]);
+        let upper = map.levels(1);
+        assert_eq!(upper.len(), 6);
+        assert!((upper[0] - 1.0).abs() < 1e-5);
+        assert!((upper[1] - 50.0 / 60.0).abs() < 1e-5);
+        assert!((upper[5] - 20.0 / 60.0).abs() < 1e-5);
+    }
+
+    #[test]
+    #[should_panic(expected = "Coverage layer out of bounds")]
+    fn layer_out_of_bounds() {
+        let grid = grid();
+        let cells = vec![CoverageCell::default(); grid.len()];
+        CoverageMap { grid, cells }.levels(2);
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(colormap(0.0), [0, 0, 0]);
+        assert_eq!(colormap(1.0), [127, 0, 0]); // Dark red, as in "jet"
+        assert_eq!(colormap(0.5)[1], 255);
+    }
+}
@@ -0,0 +1,160 @@
+// error.rs
+//! Errors of the `Simulation` entry points, for callers that must not panic.
+
+use crate::scene::SceneError;
+use crate::validation::Issue;
+
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing `path` failed
+    Io { path: String, source: io::Error },
+    Scene(SceneError),
+    /// The issues of a scene that has errors, warnings included
+    InvalidScene(Vec<Issue>),
+    /// The solution in `path` was computed for another scene
+    SceneMismatch {
+        path: String,
+        found: u64,
+        expected: u64,
+    },
+    /// A frame of the timeline does not have the antennas of the scene
+    FrameAntennas { frame: usize },
+    /// A receiver does not have one set of transfer functions per frame
+    FrameCount {
+        receiver: String,
+        found: usize,
+        expected: usize,
+    },
+    /// Instants `index - 1` and `index` of the timeline, in seconds, are not at
+    /// least one beat apart
+    Timeline { index: usize, previous: f32, time: f32 },
+    /// Transfer functions must be sorted by time
+    UnsortedTransfer { receiver: String, emitter: String },
+    UnknownAntenna(String),
+    /// A synthetic channel replaces the transfer functions, a solution cannot
+    /// be loaded with it
+    ChannelWithSolution,
+    /// The partial solution in `path` cannot be merged with the others
+    Merge { path: String, message: String },
+    /// A frequency band, in Hz, outside of what the transfer functions sample
+    InvalidBand {
+        start: f32,
+        end: f32,
+        resolution: f32,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn io(path: impl ToString, source: io::Error) -> Self {
+        Error::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+}
+
+/// Malformed content at `line` of the text file `path`, counted from 0
+pub(crate) fn invalid(path: &str, line: usize, msg: impl fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}:{}: {}", path, line + 1, msg),
+    )
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io {
+                ref path,
+                ref source,
+            } => write!(f, "{}: {}", path, source),
+            Error::Scene(ref e) => write!(f, "{}", e),
+            Error::InvalidScene(ref issues) => {
+                write!(f, "invalid scene")?;
+                for issue in issues {
+                    write!(f, "\n{}", issue)?;
+                }
+                Ok(())
+            }
+            Error::SceneMismatch {
+                ref path,
+                found,
+                expected,
+            } => write!(
+                f,
+                "{} was solved for another scene (hash {:016x}, the scene has {:016x})",
+                path, found, expected
+            ),
+            Error::FrameAntennas { frame } => {
+                write!(f, "the antennas of frame {} differ from the scene's", frame)
+            }
+            Error::FrameCount {
+                ref receiver,
+                found,
+                expected,
+            } => write!(
+                f,
+                "receiver {} has {} frames of transfer functions, the timeline {}",
+                receiver, found, expected
+            ),
+            Error::Timeline {
+                index,
+                previous,
+                time,
+            } => write!(
+                f,
+                "instant {} of the timeline ({} s) is not at least one beat after the previous one ({} s)",
+                index, time, previous
+            ),
+            Error::UnsortedTransfer {
+                ref receiver,
+                ref emitter,
+            } => write!(
+                f,
+                "transfer function from {} to {} is not sorted by time",
+                emitter, receiver
+            ),
+            Error::UnknownAntenna(ref name) => write!(f, "no antenna named {}", name),
+            Error::ChannelWithSolution => {
+                write!(f, "a synthetic channel cannot be used with a solution")
+            }
+            Error::Merge {
+                ref path,
+                ref message,
+            } => write!(f, "{} cannot be merged: {}", path, message),
+            Error::InvalidBand {
+                start,
+                end,
+                resolution,
+            } => write!(
+                f,
+                "invalid band from {} Hz to {} Hz by {} Hz, it must be within 0 Hz to {} Hz",
+                start,
+                end,
+                resolution,
+                0.5 / crate::TIME_PER_BEAT
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::Io { ref source, .. } => Some(source),
+            Error::Scene(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<SceneError> for Error {
+    fn from(e: SceneError) -> Self {
+        Error::Scene(e)
+    }
+}
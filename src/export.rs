@@ -0,0 +1,414 @@
+// export.rs
+//! Export of scenes to glTF 2.0 and Wavefront OBJ, to review them in a 3D
+//! viewer. Obstacles are tessellated and coloured by material, antennas are
+//! small octahedra: red for emitters, blue for receivers.
+
+use crate::antennas::{SceneObject, WorldDescriptor};
+use crate::blockage::Blockage;
+use crate::geometry::Primitive;
+use crate::materials::{self, Material};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use nalgebra::{Isometry3, Point3, Translation3, UnitQuaternion, Vector3};
+use ncollide3d::procedural;
+use ncollide3d::transformation;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Subdivisions around the axis of round shapes
+const SUBDIVISIONS: u32 = 24;
+/// Half size of antenna markers in meters
+const MARKER_SIZE: f32 = 0.2;
+
+const PALETTE: [[f32; 3]; 8] = [
+    [0.65, 0.65, 0.65],
+    [0.80, 0.45, 0.30],
+    [0.55, 0.75, 0.90],
+    [0.90, 0.85, 0.70],
+    [0.60, 0.45, 0.30],
+    [0.45, 0.40, 0.55],
+    [0.85, 0.80, 0.35],
+    [0.50, 0.65, 0.55],
+];
+const FOLIAGE_COLOUR: [f32; 3] = [0.20, 0.55, 0.20];
+const BODY_COLOUR: [f32; 3] = [0.90, 0.70, 0.55];
+const EMITTER_COLOUR: [f32; 3] = [0.90, 0.10, 0.10];
+const RECEIVER_COLOUR: [f32; 3] = [0.10, 0.30, 0.90];
+
+/// Triangles in the local frame of a shape
+pub struct Mesh {
+    pub points: Vec<Point3<f32>>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+/// Planes are squares of side `plane_size` centered on their origin
+pub fn tessellate(primitive: &Primitive, plane_size: f32) -> Mesh {
+    let mesh = match *primitive {
+        Primitive::Plane { normal } => {
+            // The quad is in the xy plane
+            let mut quad = procedural::quad(plane_size, plane_size, 1, 1);
+            let rotation = UnitQuaternion::rotation_between(&Vector3::z(), &normal)
+                .unwrap_or_else(|| {
+                    UnitQuaternion::from_axis_angle(&Vector3::x_axis(), std::f32::consts::PI)
+                });
+            quad.transform_by(&Isometry3::from_parts(Translation3::identity(), rotation));
+            quad
+        }
+        Primitive::Cuboid { half_extents } => procedural::cuboid(&(half_extents * 2.0)),
+        Primitive::Ball { radius } => {
+            procedural::sphere(radius * 2.0, SUBDIVISIONS, SUBDIVISIONS / 2, false)
+        }
+        Primitive::Cylinder {
+            half_height,
+            radius,
+        } => procedural::cylinder(radius * 2.0, half_height * 2.0, SUBDIVISIONS),
+        Primitive::Cone {
+            half_height,
+            radius,
+        } => procedural::cone(radius * 2.0, half_height * 2.0, SUBDIVISIONS),
+        Primitive::Capsule {
+            half_height,
+            radius,
+        } => procedural::capsule(
+            &(radius * 2.0),
+            &(half_height * 2.0),
+            SUBDIVISIONS,
+            SUBDIVISIONS / 2,
+        ),
+        Primitive::ConvexHull { ref points } => transformation::convex_hull(points),
+        Primitive::TriMesh {
+            ref points,
+            ref indices,
+        } => {
+            return Mesh {
+                points: points.clone(),
+                triangles: indices
+                    .iter()
+                    .map(|t| [t.x as u32, t.y as u32, t.z as u32])
+                    .collect(),
+            }
+        }
+    };
+
+    let indices = mesh.flat_indices();
+    Mesh {
+        points: mesh.coords,
+        triangles: indices.chunks(3).map(|t| [t[0], t[1], t[2]]).collect(),
+    }
+}
+
+fn marker() -> Mesh {
+    let s = MARKER_SIZE;
+    Mesh {
+        points: vec![
+            Point3::new(s, 0.0, 0.0),
+            Point3::new(-s, 0.0, 0.0),
+            Point3::new(0.0, s, 0.0),
+            Point3::new(0.0, -s, 0.0),
+            Point3::new(0.0, 0.0, s),
+            Point3::new(0.0, 0.0, -s),
+        ],
+        triangles: vec![
+            [0, 2, 4],
+            [2, 1, 4],
+            [1, 3, 4],
+            [3, 0, 4],
+            [2, 0, 5],
+            [1, 2, 5],
+            [3, 1, 5],
+            [0, 3, 5],
+        ],
+    }
+}
+
+/// Named colour shared by several parts
+pub struct Swatch {
+    pub name: String,
+    pub colour: [f32; 3],
+}
+
+/// Mesh placed in the scene
+pub struct Part {
+    pub name: String,
+    /// Index in `WorldDescriptor::collisions` of obstacles
+    pub object: Option<usize>,
+    /// Index in `WorldDescriptor::names` of antenna markers
+    pub antenna: Option<usize>,
+    pub swatch: usize,
+    pub mesh: Mesh,
+    pub transform: Isometry3<f32>,
+}
+
+/// Name of `material` in the built-in library, if it comes from it
+pub fn material_name(material: &Material) -> Option<String> {
+    materials::library()
+        .into_iter()
+        .find(|(_, x)| x == material)
+        .map(|(name, _)| name)
+}
+
+/// Side of the square drawn for planes: the extent of the other objects and antennas
+fn plane_size(world: &WorldDescriptor) -> f32 {
+    let finite = |x: f32| x.is_finite() && x.abs() < 1e6;
+    let corners = world
+        .collisions
+        .iter()
+        .flat_map(|(_, aabb)| vec![*aabb.mins(), *aabb.maxs()])
+        .chain(world.emitters.iter().flatten().map(|x| x.position))
+        .chain(world.receivers.iter().flatten().map(|x| x.position));
+    let extent = corners
+        .flat_map(|p| vec![p.x, p.y, p.z])
+        .filter(|&x| finite(x))
+        .fold(0.0, |acc: f32, x| acc.max(x.abs()));
+    2.0 * extent + 20.0
+}
+
+/// Obstacles and antenna markers of `world`, with their colours
+pub fn scene_parts(world: &WorldDescriptor) -> (Vec<Part>, Vec<Swatch>) {
+    let size = plane_size(world);
+    let mut swatches = vec![
+        Swatch {
+            name: "emitter".to_string(),
+            colour: EMITTER_COLOUR,
+        },
+        Swatch {
+            name: "receiver".to_string(),
+            colour: RECEIVER_COLOUR,
+        },
+    ];
+    let mut seen: Vec<(Option<Blockage>, Material)> = Vec::new();
+    let mut parts = Vec::new();
+
+    let objects = world
+        .collisions
+        .iter()
+        .map(|(object, _)| object)
+        .enumerate()
+        .filter(|(_, object)| object.receiver.is_none());
+    for (i, object) in objects {
+        let mesh = tessellate(&object.geometry().primitive(), size);
+        if mesh.triangles.is_empty() {
+            continue;
+        }
+        let key = (object.blockage, object.material().clone());
+        let swatch = match seen.iter().position(|x| *x == key) {
+            Some(k) => k + 2,
+            None => {
+                swatches.push(swatch(object, seen.len()));
+                seen.push(key);
+                swatches.len() - 1
+            }
+        };
+        parts.push(Part {
+            name: format!("object_{}", i),
+            object: Some(i),
+            antenna: None,
+            swatch,
+            mesh,
+            transform: *object.transform(),
+        });
+    }
+
+    for (a, name) in world.names.iter().enumerate() {
+        let (position, swatch) = match (&world.emitters[a], &world.receivers[a]) {
+            (Some(emitter), _) => (emitter.position, 0),
+            (_, Some(receiver)) => (receiver.position, 1),
+            _ => continue,
+        };
+        parts.push(Part {
+            name: name.clone(),
+            object: None,
+            antenna: Some(a),
+            swatch,
+            mesh: marker(),
+            transform: Isometry3::from_parts(
+                Translation3::from(position.coords),
+                UnitQuaternion::identity(),
+            ),
+        });
+    }
+
+    (parts, swatches)
+}
+
+fn swatch(object: &SceneObject, index: usize) -> Swatch {
+    match object.blockage {
+        Some(Blockage::Foliage(_)) => Swatch {
+            name: format!("foliage_{}", index),
+            colour: FOLIAGE_COLOUR,
+        },
+        Some(Blockage::Body(_)) => Swatch {
+            name: format!("body_{}", index),
+            colour: BODY_COLOUR,
+        },
+        None => Swatch {
+            name: material_name(object.material())
+                .unwrap_or_else(|| format!("material_{}", index)),
+            colour: PALETTE[index % PALETTE.len()],
+        },
+    }
+}
+
+/// JSON string literal
+fn quote(s: &str) -> String {
+    let mut res = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            c if c.is_control() => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+/// Write `world` to `path` (.gltf) and its buffer next to it (.bin)
+pub fn write_gltf(world: &WorldDescriptor, path: &str) -> io::Result<()> {
+    let (parts, swatches) = scene_parts(world);
+    let bin_path = Path::new(path).with_extension("bin");
+    let bin_name = bin_path
+        .file_name()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut bin = BufWriter::new(File::create(&bin_path)?);
+    let mut offset = 0;
+    let mut views = Vec::new();
+    let mut accessors = Vec::new();
+    let mut meshes = Vec::new();
+    let mut nodes = Vec::new();
+
+    for (i, part) in parts.iter().enumerate() {
+        let (mut min, mut max) = (part.mesh.points[0], part.mesh.points[0]);
+        for p in &part.mesh.points {
+            bin.write_f32::<LittleEndian>(p.x)?;
+            bin.write_f32::<LittleEndian>(p.y)?;
+            bin.write_f32::<LittleEndian>(p.z)?;
+            min = Point3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Point3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+        let length = part.mesh.points.len() * 12;
+        views.push(format!(
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":34962}}"#,
+            offset, length
+        ));
+        accessors.push(format!(
+            r#"{{"bufferView":{},"componentType":5126,"count":{},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}}"#,
+            2 * i,
+            part.mesh.points.len(),
+            min.x,
+            min.y,
+            min.z,
+            max.x,
+            max.y,
+            max.z
+        ));
+        offset += length;
+
+        for t in &part.mesh.triangles {
+            for &v in t {
+                bin.write_u32::<LittleEndian>(v)?;
+            }
+        }
+        let length = part.mesh.triangles.len() * 12;
+        views.push(format!(
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":34963}}"#,
+            offset, length
+        ));
+        accessors.push(format!(
+            r#"{{"bufferView":{},"componentType":5125,"count":{},"type":"SCALAR"}}"#,
+            2 * i + 1,
+            part.mesh.triangles.len() * 3
+        ));
+        offset += length;
+
+        meshes.push(format!(
+            r#"{{"primitives":[{{"attributes":{{"POSITION":{}}},"indices":{},"material":{}}}]}}"#,
+            2 * i,
+            2 * i + 1,
+            part.swatch
+        ));
+        let (t, r) = (part.transform.translation.vector, part.transform.rotation);
+        nodes.push(format!(
+            r#"{{"name":{},"mesh":{},"translation":[{},{},{}],"rotation":[{},{},{},{}]}}"#,
+            quote(&part.name), i, t.x, t.y, t.z, r.i, r.j, r.k, r.w
+        ));
+    }
+    bin.flush()?;
+
+    let materials: Vec<String> = swatches
+        .iter()
+        .map(|s| {
+            format!(
+                r#"{{"name":{},"doubleSided":true,"pbrMetallicRoughness":{{"baseColorFactor":[{},{},{},1.0],"metallicFactor":0.0,"roughnessFactor":1.0}}}}"#,
+                quote(&s.name), s.colour[0], s.colour[1], s.colour[2]
+            )
+        })
+        .collect();
+    let scene_nodes: Vec<String> = (0..parts.len()).map(|i| i.to_string()).collect();
+
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "{{")?;
+    writeln!(file, r#""asset":{{"version":"2.0","generator":"tipe2"}},"#)?;
+    writeln!(file, r#""scene":0,"#)?;
+    writeln!(file, r#""scenes":[{{"nodes":[{}]}}],"#, scene_nodes.join(","))?;
+    writeln!(file, r#""nodes":[{}],"#, nodes.join(",\n"))?;
+    writeln!(file, r#""meshes":[{}],"#, meshes.join(",\n"))?;
+    writeln!(file, r#""materials":[{}],"#, materials.join(",\n"))?;
+    writeln!(file, r#""accessors":[{}],"#, accessors.join(",\n"))?;
+    writeln!(file, r#""bufferViews":[{}],"#, views.join(",\n"))?;
+    writeln!(
+        file,
+        r#""buffers":[{{"byteLength":{},"uri":{}}}]"#,
+        offset,
+        quote(&bin_name)
+    )?;
+    writeln!(file, "}}")?;
+    Ok(())
+}
+
+/// Write `world` to `path` (.obj) and its materials next to it (.mtl).
+/// Vertices are in world coordinates.
+pub fn write_obj(world: &WorldDescriptor, path: &str) -> io::Result<()> {
+    let (parts, swatches) = scene_parts(world);
+    let mtl_path = Path::new(path).with_extension("mtl");
+    let mtl_name = mtl_path
+        .file_name()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut mtl = BufWriter::new(File::create(&mtl_path)?);
+    for s in &swatches {
+        writeln!(mtl, "newmtl {}", s.name)?;
+        writeln!(mtl, "Kd {} {} {}", s.colour[0], s.colour[1], s.colour[2])?;
+        writeln!(mtl)?;
+    }
+    mtl.flush()?;
+
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "mtllib {}", mtl_name)?;
+    let mut first = 1;
+    for part in &parts {
+        writeln!(file, "o {}", part.name.replace(char::is_whitespace, "_"))?;
+        writeln!(file, "usemtl {}", swatches[part.swatch].name)?;
+        for p in &part.mesh.points {
+            let p = part.transform * p;
+            writeln!(file, "v {} {} {}", p.x, p.y, p.z)?;
+        }
+        for t in &part.mesh.triangles {
+            writeln!(
+                file,
+                "f {} {} {}",
+                first + t[0] as usize,
+                first + t[1] as usize,
+                first + t[2] as usize
+            )?;
+        }
+        first += part.mesh.points.len();
+    }
+    Ok(())
+}
@@ -0,0 +1,192 @@
+// fingerprint.rs
+//! Canonical hash of a scene, stored with solutions so that a solution is
+//! not reused with another geometry. FNV-1a is used as it is stable across
+//! platforms and compiler versions, unlike `std::collections::hash_map::DefaultHasher`.
+
+use crate::antennas::WorldDescriptor;
+use crate::geometry::Primitive;
+use crate::scene::{SceneDescription, SceneError};
+use crate::waves::TracerConfig;
+use crate::MAX_FREQUENCY;
+
+use std::path::Path;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+struct Fnv(u64);
+
+impl Fnv {
+    fn bytes(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= u64::from(b);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn u64(&mut self, x: u64) {
+        self.bytes(&x.to_le_bytes());
+    }
+
+    fn usize(&mut self, x: usize) {
+        self.u64(x as u64);
+    }
+
+    fn f32(&mut self, x: f32) {
+        // 0.0 and -0.0 describe the same scene
+        let x = if x == 0.0 { 0.0f32 } else { x };
+        self.bytes(&x.to_bits().to_le_bytes());
+    }
+
+    fn floats(&mut self, xs: &[f32]) {
+        self.usize(xs.len());
+        for &x in xs {
+            self.f32(x);
+        }
+    }
+
+    fn str(&mut self, s: &str) {
+        self.usize(s.len());
+        self.bytes(s.as_bytes());
+    }
+
+    /// Values without a hand-written encoding, through their RON representation
+    fn ron<T: serde::Serialize>(&mut self, value: &T) {
+        let text = ron::ser::to_string(value).expect("Failed to serialize for hashing");
+        self.str(&text);
+    }
+
+    fn primitive(&mut self, primitive: &Primitive) {
+        match *primitive {
+            Primitive::Plane { normal } => {
+                self.usize(0);
+                self.floats(normal.as_slice());
+            }
+            Primitive::Cuboid { half_extents } => {
+                self.usize(1);
+                self.floats(half_extents.as_slice());
+            }
+            Primitive::Ball { radius } => {
+                self.usize(2);
+                self.f32(radius);
+            }
+            Primitive::Cylinder {
+                half_height,
+                radius,
+            } => {
+                self.usize(3);
+                self.floats(&[half_height, radius]);
+            }
+            Primitive::Cone {
+                half_height,
+                radius,
+            } => {
+                self.usize(4);
+                self.floats(&[half_height, radius]);
+            }
+            Primitive::Capsule {
+                half_height,
+                radius,
+            } => {
+                self.usize(5);
+                self.floats(&[half_height, radius]);
+            }
+            Primitive::ConvexHull { ref points } => {
+                self.usize(6);
+                self.usize(points.len());
+                for p in points {
+                    self.floats(p.coords.as_slice());
+                }
+            }
+            Primitive::TriMesh {
+                ref points,
+                ref indices,
+            } => {
+                self.usize(7);
+                self.usize(points.len());
+                for p in points {
+                    self.floats(p.coords.as_slice());
+                }
+                self.usize(indices.len());
+                for t in indices {
+                    self.usize(t.x);
+                    self.usize(t.y);
+                    self.usize(t.z);
+                }
+            }
+        }
+    }
+}
+
+/// Hash of the obstacles, materials, antenna positions and tracer configuration.
+/// Signal kinds are left out as they do not change the transfer functions.
+/// Must be computed before solving, as tracing consumes the obstacles.
+pub fn scene_hash(world: &WorldDescriptor, config: &TracerConfig) -> u64 {
+    let mut h = Fnv(FNV_OFFSET);
+    h.f32(MAX_FREQUENCY);
+    // Recorded paths do not change the transfer functions, and solutions
+    // traced with other seeds or in parts are merged together
+    h.f32(config.receiver_radius);
+    h.ron(&config.atmosphere);
+
+    let objects: Vec<_> = world
+        .collisions
+        .iter()
+        .map(|(object, _)| object)
+        .filter(|object| object.receiver.is_none())
+        .collect();
+    h.usize(objects.len());
+    for object in objects {
+        h.primitive(&object.geometry().primitive());
+        let transform = object.transform();
+        h.floats(transform.translation.vector.as_slice());
+        h.floats(transform.rotation.coords.as_slice());
+        h.ron(object.material());
+        h.ron(&object.blockage);
+    }
+
+    h.usize(world.names.len());
+    for (a, name) in world.names.iter().enumerate() {
+        h.str(name);
+        if let Some(ref emitter) = world.emitters[a] {
+            h.usize(0);
+            h.floats(emitter.position.coords.as_slice());
+            h.f32(emitter.max_power);
+        }
+        if let Some(ref receiver) = world.receivers[a] {
+            h.usize(1);
+            h.floats(receiver.position.coords.as_slice());
+        }
+    }
+
+    h.0
+}
+
+/// Hash of a scene with moving objects, from the hashes of its frames
+pub fn timeline_hash(frames: &[(f32, u64)]) -> u64 {
+    let mut h = Fnv(FNV_OFFSET);
+    h.usize(frames.len());
+    for &(time, hash) in frames {
+        h.f32(time);
+        h.u64(hash);
+    }
+    h.0
+}
+
+/// Hash of a scene file as solved with `config`: the hash of the scene, or
+/// of its frames when objects move. Relative paths are resolved from `dir`.
+pub fn description_hash(
+    scene: &SceneDescription,
+    dir: &Path,
+    config: &TracerConfig,
+) -> Result<u64, SceneError> {
+    let times = scene.times();
+    if times.is_empty() {
+        return Ok(scene_hash(&scene.build(dir)?, config));
+    }
+    let mut hashes = Vec::with_capacity(times.len());
+    for time in times {
+        hashes.push((time, scene_hash(&scene.build_at(dir, time)?, config)));
+    }
+    Ok(timeline_hash(&hashes))
+}
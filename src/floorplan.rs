@@ -0,0 +1,310 @@
+// floorplan.rs
+//! Buildings extruded from a 2D floor plan. Plan coordinates are (x, z),
+//! storeys are stacked along y.
+
+use crate::antennas::{create_bvt_tuple, SceneObject};
+use crate::materials::{Material, MaterialLibrary};
+use crate::scene::{MaterialDescription, SceneError};
+
+use nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector2, Vector3};
+use ncollide3d::bounding_volume::aabb::AABB;
+use ncollide3d::shape::Cuboid;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FloorPlan {
+    /// Height of a storey, slab included
+    pub floor_height: f32,
+    pub storeys: usize,
+    pub slab_thickness: f32,
+    pub slab_material: MaterialDescription,
+    pub walls: Vec<Wall>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Wall {
+    pub points: Vec<[f32; 2]>,
+    /// Defaults to the thickness of the material
+    #[serde(default)]
+    pub thickness: Option<f32>,
+    pub material: MaterialDescription,
+    #[serde(default)]
+    pub openings: Vec<Opening>,
+}
+
+/// Door or window, repeated on every storey
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Opening {
+    /// Index of the segment of the polyline
+    pub segment: usize,
+    /// Distance from the start of the segment
+    pub offset: f32,
+    pub width: f32,
+    /// Height of the bottom of the opening, 0 for doors
+    #[serde(default)]
+    pub sill: f32,
+    pub height: f32,
+}
+
+type Objects = Vec<(SceneObject, AABB<f32>)>;
+
+/// Distance below which plan points are considered the same, in meters
+const JUNCTION_TOLERANCE: f32 = 1e-3;
+
+fn slab(
+    transform: &Isometry3<f32>,
+    center: Vector3<f32>,
+    half_extents: Vector3<f32>,
+    yaw: f32,
+    material: &Material,
+) -> (SceneObject, AABB<f32>) {
+    let local = Isometry3::from_parts(
+        Translation3::from(center),
+        UnitQuaternion::from_axis_angle(&Vector3::y_axis(), yaw),
+    );
+    create_bvt_tuple(&Cuboid::new(half_extents), transform * local, material.clone())
+}
+
+impl FloorPlan {
+    /// Length to cut from the end of wall `w` at `point`, going along `dir`,
+    /// when it ends inside a segment of another wall
+    fn junction(
+        &self,
+        w: usize,
+        point: Vector2<f32>,
+        dir: Vector2<f32>,
+        thicknesses: &[f32],
+    ) -> f32 {
+        let mut cut: f32 = 0.0;
+        for (v, wall) in self.walls.iter().enumerate().filter(|x| x.0 != w) {
+            for segment in wall.points.windows(2) {
+                let a = Vector2::from(segment[0]);
+                let other = Vector2::from(segment[1]) - a;
+                let length = other.norm();
+                let along = (point - a).dot(&other) / length;
+                let across = (point - a).perp(&other) / length;
+                let sin = dir.perp(&other).abs() / length;
+                if across.abs() < JUNCTION_TOLERANCE
+                    && along > JUNCTION_TOLERANCE
+                    && along < length - JUNCTION_TOLERANCE
+                    && sin > JUNCTION_TOLERANCE
+                {
+                    cut = cut.max(thicknesses[v] / 2.0 / sin);
+                }
+            }
+        }
+        cut
+    }
+
+    pub fn load(path: &str) -> Result<Self, SceneError> {
+        let file = std::fs::File::open(path).map_err(|e| SceneError::new(path, e))?;
+        ron::de::from_reader(std::io::BufReader::new(file)).map_err(|e| SceneError::new(path, e))
+    }
+
+    /// Wall slabs, floors and a roof. `entry` prefixes the location of errors.
+    pub fn extrude(
+        &self,
+        entry: &str,
+        transform: &Isometry3<f32>,
+        library: &MaterialLibrary,
+    ) -> Result<Objects, SceneError> {
+        let wall_height = self.floor_height - self.slab_thickness;
+        if self.walls.is_empty() {
+            return Err(SceneError::new(entry, "a floor plan needs walls"));
+        }
+        if self.storeys == 0 || self.slab_thickness <= 0.0 || wall_height <= 0.0 {
+            return Err(SceneError::new(
+                entry,
+                "need at least one storey and a slab thinner than the storey",
+            ));
+        }
+
+        let slab_material = self.slab_material.resolve(entry, library)?;
+        let mut materials = Vec::with_capacity(self.walls.len());
+        let mut thicknesses = Vec::with_capacity(self.walls.len());
+        for (w, wall) in self.walls.iter().enumerate() {
+            let entry = format!("{}.walls[{}]", entry, w);
+            let material = wall.material.resolve(&entry, library)?;
+            let thickness = wall.thickness.or(material.thickness).unwrap_or(0.0);
+            if wall.points.len() < 2 || thickness <= 0.0 {
+                return Err(SceneError::new(
+                    entry,
+                    "a wall needs 2 points and a positive thickness",
+                ));
+            }
+            materials.push(material);
+            thicknesses.push(thickness);
+        }
+
+        let mut res = Vec::new();
+        let thickest = thicknesses.iter().cloned().fold(0.0, f32::max);
+        let mut min = Vector2::new(f32::INFINITY, f32::INFINITY);
+        let mut max = -min;
+
+        for (w, wall) in self.walls.iter().enumerate() {
+            let entry = format!("{}.walls[{}]", entry, w);
+            let (material, thickness) = (&materials[w], thicknesses[w]);
+
+            let mut pieces = Vec::new();
+            for s in 0..wall.points.len() - 1 {
+                let a = Vector2::from(wall.points[s]);
+                let b = Vector2::from(wall.points[s + 1]);
+                let length = (b - a).norm();
+                if length == 0.0 {
+                    return Err(SceneError::new(&entry, format!("segment {} is empty", s)));
+                }
+
+                // Overlap at the joints so that corners are closed, stop at
+                // the face of the walls met in a T
+                let dir = (b - a) / length;
+                let start = if s > 0 {
+                    -thickness / 2.0
+                } else {
+                    self.junction(w, a, dir, &thicknesses)
+                };
+                let end = if s + 2 < wall.points.len() {
+                    length + thickness / 2.0
+                } else {
+                    length - self.junction(w, b, dir, &thicknesses)
+                };
+                if end <= start {
+                    return Err(SceneError::new(
+                        &entry,
+                        format!("segment {} is shorter than the walls it meets", s),
+                    ));
+                }
+
+                let mut openings: Vec<&Opening> =
+                    wall.openings.iter().filter(|o| o.segment == s).collect();
+                openings.sort_by(|x, y| x.offset.partial_cmp(&y.offset).unwrap());
+
+                // (along the segment, from the floor) intervals
+                let mut cursor = start;
+                for (o, opening) in openings.iter().enumerate() {
+                    let top = opening.sill + opening.height;
+                    if opening.offset < cursor.max(0.0)
+                        || opening.offset + opening.width > end.min(length)
+                        || opening.width <= 0.0
+                        || opening.sill < 0.0
+                        || opening.height <= 0.0
+                        || top > wall_height
+                    {
+                        return Err(SceneError::new(
+                            &entry,
+                            format!("opening {} of segment {} does not fit", o, s),
+                        ));
+                    }
+
+                    let right = opening.offset + opening.width;
+                    pieces.push((s, (cursor, opening.offset), (0.0, wall_height)));
+                    if opening.sill > 0.0 {
+                        pieces.push((s, (opening.offset, right), (0.0, opening.sill)));
+                    }
+                    if top < wall_height {
+                        pieces.push((s, (opening.offset, right), (top, wall_height)));
+                    }
+                    cursor = right;
+                }
+                pieces.push((s, (cursor, end), (0.0, wall_height)));
+
+                for p in &[a, b] {
+                    min = Vector2::new(min.x.min(p.x), min.y.min(p.y));
+                    max = Vector2::new(max.x.max(p.x), max.y.max(p.y));
+                }
+            }
+
+            for storey in 0..self.storeys {
+                let base = storey as f32 * self.floor_height + self.slab_thickness;
+                for &(s, (u0, u1), (v0, v1)) in &pieces {
+                    if u1 - u0 <= 0.0 {
+                        continue;
+                    }
+                    let a = Vector2::from(wall.points[s]);
+                    let dir = (Vector2::from(wall.points[s + 1]) - a).normalize();
+                    let center = a + dir * (u0 + u1) / 2.0;
+                    res.push(slab(
+                        transform,
+                        Vector3::new(center.x, base + (v0 + v1) / 2.0, center.y),
+                        Vector3::new((u1 - u0) / 2.0, (v1 - v0) / 2.0, thickness / 2.0),
+                        -dir.y.atan2(dir.x),
+                        material,
+                    ));
+                }
+            }
+        }
+
+        // Floors and roof cover the bounding rectangle of the walls
+        let margin = thickest / 2.0;
+        let center = (min + max) / 2.0;
+        let half = (max - min) / 2.0 + Vector2::new(margin, margin);
+        for level in 0..=self.storeys {
+            res.push(slab(
+                transform,
+                Vector3::new(
+                    center.x,
+                    level as f32 * self.floor_height + self.slab_thickness / 2.0,
+                    center.y,
+                ),
+                Vector3::new(half.x, self.slab_thickness / 2.0, half.y),
+                0.0,
+                &slab_material,
+            ));
+        }
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::SceneDescription;
+    use crate::validation::validate;
+
+    fn wall(points: Vec<[f32; 2]>, thickness: f32) -> Wall {
+        Wall {
+            points,
+            thickness: Some(thickness),
+            material: MaterialDescription::Dielectric(2.0),
+            openings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn junctions() {
+        let plan = FloorPlan {
+            floor_height: 3.0,
+            storeys: 1,
+            slab_thickness: 0.2,
+            slab_material: MaterialDescription::Dielectric(2.0),
+            walls: vec![
+                wall(vec![[0.0, 0.0], [10.0, 0.0]], 0.3),
+                wall(vec![[5.0, 0.0], [5.0, 5.0]], 0.1),
+                wall(vec![[0.0, 3.0], [5.0, 3.0]], 0.1),
+            ],
+        };
+        let thicknesses = [0.3, 0.1, 0.1];
+        let (x, z) = (Vector2::x(), Vector2::y());
+        // Inside a segment, at the half thickness of the wall met
+        let cut = |w, point: [f32; 2], dir| {
+            plan.junction(w, Vector2::from(point), dir, &thicknesses)
+        };
+        assert!((cut(1, [5.0, 0.0], z) - 0.15).abs() < 1e-6);
+        assert!((cut(2, [5.0, 3.0], x) - 0.05).abs() < 1e-6);
+        // Free ends and the ends of other walls are left as they are
+        assert_eq!(cut(1, [5.0, 5.0], z), 0.0);
+        assert_eq!(cut(2, [0.0, 3.0], x), 0.0);
+        assert_eq!(cut(0, [0.0, 0.0], x), 0.0);
+        // Oblique walls cut further
+        let diagonal = Vector2::new(1.0, 1.0).normalize();
+        assert!((cut(1, [5.0, 0.0], diagonal) - 0.15 * 2f32.sqrt()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn office() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/office.ron");
+        let (scene, dir) = SceneDescription::load(path).unwrap();
+        let world = scene.build(&dir).unwrap();
+        let issues = validate(&world, &scene.tracer);
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+}
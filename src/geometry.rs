@@ -0,0 +1,126 @@
+// geometry.rs
+//! Shapes that can be placed in a scene. Besides ray casting, the tracer's
+//! tooling needs point queries and a plain description of every shape.
+
+use nalgebra::{Point3, Vector3};
+use ncollide3d::query::{PointQuery, RayCast};
+use ncollide3d::shape::{
+    Ball, Capsule, Cone, ConvexHull, Cuboid, Cylinder, Plane, SupportMap, TriMesh,
+};
+
+/// Parameters of a shape, in its local frame
+#[derive(Clone, Debug)]
+pub enum Primitive {
+    Plane { normal: Vector3<f32> },
+    Cuboid { half_extents: Vector3<f32> },
+    Ball { radius: f32 },
+    Cylinder { half_height: f32, radius: f32 },
+    Cone { half_height: f32, radius: f32 },
+    Capsule { half_height: f32, radius: f32 },
+    ConvexHull { points: Vec<Point3<f32>> },
+    TriMesh {
+        points: Vec<Point3<f32>>,
+        indices: Vec<Point3<usize>>,
+    },
+}
+
+pub trait SceneGeometry: RayCast<f32> + PointQuery<f32> + Send + Sync {
+    fn primitive(&self) -> Primitive;
+
+    /// Convex shapes, used for overlap queries
+    fn as_support_map(&self) -> Option<&dyn SupportMap<f32>> {
+        None
+    }
+}
+
+impl SceneGeometry for Plane<f32> {
+    fn primitive(&self) -> Primitive {
+        Primitive::Plane {
+            normal: self.normal().into_inner(),
+        }
+    }
+}
+
+impl SceneGeometry for Cuboid<f32> {
+    fn primitive(&self) -> Primitive {
+        Primitive::Cuboid {
+            half_extents: *self.half_extents(),
+        }
+    }
+
+    fn as_support_map(&self) -> Option<&dyn SupportMap<f32>> {
+        Some(self)
+    }
+}
+
+impl SceneGeometry for Ball<f32> {
+    fn primitive(&self) -> Primitive {
+        Primitive::Ball {
+            radius: self.radius(),
+        }
+    }
+
+    fn as_support_map(&self) -> Option<&dyn SupportMap<f32>> {
+        Some(self)
+    }
+}
+
+impl SceneGeometry for Cylinder<f32> {
+    fn primitive(&self) -> Primitive {
+        Primitive::Cylinder {
+            half_height: self.half_height(),
+            radius: self.radius(),
+        }
+    }
+
+    fn as_support_map(&self) -> Option<&dyn SupportMap<f32>> {
+        Some(self)
+    }
+}
+
+impl SceneGeometry for Cone<f32> {
+    fn primitive(&self) -> Primitive {
+        Primitive::Cone {
+            half_height: self.half_height(),
+            radius: self.radius(),
+        }
+    }
+
+    fn as_support_map(&self) -> Option<&dyn SupportMap<f32>> {
+        Some(self)
+    }
+}
+
+impl SceneGeometry for Capsule<f32> {
+    fn primitive(&self) -> Primitive {
+        Primitive::Capsule {
+            half_height: self.half_height(),
+            radius: self.radius(),
+        }
+    }
+
+    fn as_support_map(&self) -> Option<&dyn SupportMap<f32>> {
+        Some(self)
+    }
+}
+
+impl SceneGeometry for ConvexHull<f32> {
+    fn primitive(&self) -> Primitive {
+        Primitive::ConvexHull {
+            points: self.points().to_vec(),
+        }
+    }
+
+    fn as_support_map(&self) -> Option<&dyn SupportMap<f32>> {
+        Some(self)
+    }
+}
+
+impl SceneGeometry for TriMesh<f32> {
+    fn primitive(&self) -> Primitive {
+        Primitive::TriMesh {
+            points: self.points().to_vec(),
+            indices: self.faces().iter().map(|f| f.indices).collect(),
+        }
+    }
+}
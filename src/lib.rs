@@ -0,0 +1,68 @@
+//! Ray-traced propagation of electromagnetic waves.
+//!
+//! A scene is traced once into transfer functions between antennas, which are
+//! then used to simulate the signals beat by beat:
+//!
+//! ```no_run
+//! let sim = tipe2::SimulationBuilder::from_file("scenes/ofdm.ron")?
+//!     .beats(0x20000)
+//!     .run()?;
+//! let transfer = sim.transfer("ofdm_rec", "ofdm_emit");
+//! # Ok::<(), tipe2::Error>(())
+//! ```
+
+extern crate crossbeam_channel;
+extern crate crossbeam_deque;
+extern crate nalgebra;
+extern crate ncollide3d;
+extern crate rayon;
+extern crate rustfft;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate bit_vec;
+extern crate chrono;
+extern crate itertools;
+
+pub mod antennas;
+pub mod atmosphere;
+pub mod blockage;
+pub mod channels;
+pub mod city;
+pub mod coverage;
+pub mod error;
+pub mod export;
+pub mod fingerprint;
+pub mod floorplan;
+pub mod geometry;
+pub mod materials;
+pub mod merge;
+mod mesh;
+pub mod pruning;
+pub mod response;
+mod ring_buffer;
+pub mod scene;
+pub mod simulation;
+pub mod solution;
+pub mod statistics;
+pub mod svg;
+pub mod systems;
+mod terrain;
+#[cfg(test)]
+mod test_utils;
+pub mod validation;
+pub mod waves;
+
+pub use crate::antennas::SignalEvent;
+pub use crate::error::{Error, Result};
+pub use crate::scene::SceneDescription;
+pub use crate::simulation::{Simulation, SimulationBuilder};
+pub use crate::waves::TracerConfig;
+
+pub type Float = f32;
+
+pub const WAVE_VELOCITY: Float = 299_792_458.; // meters per second
+pub const MAX_FREQUENCY: Float = 60_000_000_000.;
+pub const TIME_PER_BEAT: Float = 1. / MAX_FREQUENCY; // seconds
+
+pub const CHANNEL_BOUND: usize = 65536;
@@ -1,118 +1,469 @@
-#![allow(dead_code)]
+use tipe2::{
+    channels, coverage, export, fingerprint, merge, pruning, response, scene, simulation, solution,
+    statistics, svg, systems, waves,
+};
 
-extern crate clap;
-extern crate crossbeam_channel;
-extern crate crossbeam_deque;
-extern crate nalgebra;
-extern crate ncollide3d;
-extern crate rayon;
-extern crate rustfft;
-extern crate serde;
-#[macro_use]
-extern crate serde_derive;
-#[macro_use]
-extern crate lazy_static;
-extern crate bit_vec;
-extern crate chrono;
-extern crate itertools;
-
-mod antennas;
-mod constants;
-mod ring_buffer;
-mod simulation;
-mod systems;
-mod waves;
-mod world;
+use specs::ReadStorage;
 
-use nalgebra::Point3;
+/// Report a fatal error and exit
+fn fail<T>(e: impl std::fmt::Display) -> T {
+    eprintln!("{}", e);
+    std::process::exit(1)
+}
 
-use specs::ReadStorage;
+fn main() {
+    let matches = clap::App::new("tipe2")
+        .about("Ray-traced propagation of electromagnetic waves")
+        .arg(
+            clap::Arg::with_name("scene")
+                .help("Scene file to simulate")
+                .default_value("scenes/ofdm.ron"),
+        )
+        .arg(
+            clap::Arg::with_name("export")
+                .long("export")
+                .value_name("FILE")
+                .help("Export the scene to glTF (.gltf) or OBJ (.obj) instead of simulating it"),
+        )
+        .arg(
+            clap::Arg::with_name("svg")
+                .long("svg")
+                .value_name("FILE")
+                .help("Draw the scene seen from above once solved"),
+        )
+        .arg(
+            clap::Arg::with_name("paths")
+                .long("paths")
+                .value_name("N")
+                .help("Record and draw the N strongest paths of each link"),
+        )
+        .arg(
+            clap::Arg::with_name("save")
+                .long("save")
+                .value_name("FILE")
+                .help("Save the solution once solved, in RON if FILE ends with .ron, in binary otherwise"),
+        )
+        .arg(
+            clap::Arg::with_name("load")
+                .long("load")
+                .value_name("FILE")
+                .help("Load a solution of the scene instead of solving it"),
+        )
+        .arg(
+            clap::Arg::with_name("convert")
+                .long("convert")
+                .value_names(&["IN", "OUT"])
+                .help("Convert a solution between RON (.ron) and binary, then exit"),
+        )
+        .arg(
+            clap::Arg::with_name("merge")
+                .long("merge")
+                .value_names(&["OUT", "IN"])
+                .min_values(2)
+                .help("Merge the partial solutions IN... into OUT, then exit"),
+        )
+        .arg(
+            clap::Arg::with_name("normalization")
+                .long("normalization")
+                .possible_values(&["rays", "sum", "average"])
+                .default_value("rays")
+                .help("How --merge combines the parts: by rays traced, sum or average"),
+        )
+        .arg(
+            clap::Arg::with_name("emitters")
+                .long("emitters")
+                .value_name("NAMES")
+                .use_delimiter(true)
+                .help("Only trace these emitters, for a solve split into jobs"),
+        )
+        .arg(
+            clap::Arg::with_name("rays")
+                .long("rays")
+                .value_names(&["START", "END"])
+                .help("Only trace the rays of indices START to END of each emitter"),
+        )
+        .arg(
+            clap::Arg::with_name("seed")
+                .long("seed")
+                .value_name("N")
+                .help("Seed of the random choices of the tracer, or of the fading of --channel"),
+        )
+        .arg(
+            clap::Arg::with_name("prune-threshold")
+                .long("prune-threshold")
+                .value_name("DB")
+                .help("Remove the taps that many dB below the strongest tap of their link"),
+        )
+        .arg(
+            clap::Arg::with_name("prune-energy")
+                .long("prune-energy")
+                .value_name("FRACTION")
+                .conflicts_with("prune-threshold")
+                .help("Keep the strongest taps holding this fraction of the energy of their link"),
+        )
+        .arg(
+            clap::Arg::with_name("prune-cluster")
+                .long("prune-cluster")
+                .value_name("BEATS")
+                .conflicts_with_all(&["prune-threshold", "prune-energy"])
+                .help("Merge the taps less than BEATS apart into one"),
+        )
+        .arg(
+            clap::Arg::with_name("response")
+                .long("response")
+                .value_names(&["RECEIVER", "EMITTER", "FILE"])
+                .help("Write the frequency response of a link to FILE and its power-delay profile next to it, in CSV or NPY (.npy), then exit"),
+        )
+        .arg(
+            clap::Arg::with_name("band")
+                .long("band")
+                .value_names(&["START", "END"])
+                .requires("response")
+                .help("Band of the frequency response in Hz, up to the Nyquist frequency by default"),
+        )
+        .arg(
+            clap::Arg::with_name("resolution")
+                .long("resolution")
+                .value_name("HZ")
+                .requires("response")
+                .help("Largest spacing of the frequencies of the response, 10 MHz by default"),
+        )
+        .arg(
+            clap::Arg::with_name("statistics")
+                .long("statistics")
+                .value_name("FILE")
+                .min_values(0)
+                .max_values(1)
+                .help("Print the delay spread, path loss and K-factor of each link, write them to FILE in CSV if given, then exit"),
+        )
+        .arg(
+            clap::Arg::with_name("excess-threshold")
+                .long("excess-threshold")
+                .value_name("DB")
+                .default_value("30")
+                .help("Taps counted by the max excess delay of --statistics, in dB below the strongest"),
+        )
+        .arg(
+            clap::Arg::with_name("channel")
+                .long("channel")
+                .value_name("MODEL")
+                .possible_values(&["tdl-a", "tdl-b", "tdl-c", "exponential", "rayleigh", "rician"])
+                .conflicts_with("load")
+                .help("Use a synthetic channel between every pair of antennas instead of tracing the scene, drawn with --seed"),
+        )
+        .arg(
+            clap::Arg::with_name("delay-spread")
+                .long("delay-spread")
+                .value_name("NS")
+                .default_value("100")
+                .help("RMS delay spread of --channel, in ns"),
+        )
+        .arg(
+            clap::Arg::with_name("taps")
+                .long("taps")
+                .value_name("N")
+                .default_value("16")
+                .help("Taps of the exponential, Rayleigh and Rician channels"),
+        )
+        .arg(
+            clap::Arg::with_name("k-factor")
+                .long("k-factor")
+                .value_name("DB")
+                .default_value("6")
+                .help("K-factor of the first tap of the Rician channel"),
+        )
+        .arg(
+            clap::Arg::with_name("path-loss")
+                .long("path-loss")
+                .value_name("DB")
+                .default_value("0")
+                .help("Mean path loss of --channel"),
+        )
+        .arg(
+            clap::Arg::with_name("coverage")
+                .long("coverage")
+                .value_name("FILE")
+                .help("Trace the received power over a grid of cells, in CSV or as a PGM (.pgm) or PPM (.ppm) image, then exit"),
+        )
+        .arg(
+            clap::Arg::with_name("grid-origin")
+                .long("grid-origin")
+                .value_names(&["X", "Y", "Z"])
+                .allow_hyphen_values(true)
+                .use_delimiter(true)
+                .default_value("-16,0,-16")
+                .help("Center of the first cell of --coverage"),
+        )
+        .arg(
+            clap::Arg::with_name("grid-step")
+                .long("grid-step")
+                .value_name("METERS")
+                .default_value("0.5")
+                .help("Size of the cells of --coverage"),
+        )
+        .arg(
+            clap::Arg::with_name("grid-dims")
+                .long("grid-dims")
+                .value_names(&["NX", "NY", "NZ"])
+                .use_delimiter(true)
+                .default_value("64,1,64")
+                .help("Cells of --coverage along each axis, NY = 1 for a 2D map"),
+        )
+        .arg(
+            clap::Arg::with_name("layer")
+                .long("layer")
+                .value_name("N")
+                .default_value("0")
+                .help("Horizontal layer of the grid drawn in the PGM and PPM images"),
+        )
+        .arg(
+            clap::Arg::with_name("uncompressed")
+                .long("uncompressed")
+                .help("With --convert, write the taps of binary solutions uncompressed"),
+        )
+        .get_matches();
 
-pub type Float = f32;
+    if let Some(mut files) = matches.values_of("convert") {
+        let (input, output) = (files.next().unwrap(), files.next().unwrap());
+        solution::convert(input, output, !matches.is_present("uncompressed")).unwrap_or_else(
+            |e| fail(format!("{} -> {}: {}", input, output, e)),
+        );
+        return;
+    }
 
-pub const WAVE_VELOCITY: Float = 299_792_458.; // meters per second
-pub const MAX_FREQUENCY: Float = 60_000_000_000.;
-pub const TIME_PER_BEAT: Float = 1. / MAX_FREQUENCY; // seconds
+    if let Some(files) = matches.values_of("merge") {
+        let files: Vec<&str> = files.collect();
+        let normalization = match matches.value_of("normalization") {
+            Some("sum") => merge::Normalization::Sum,
+            Some("average") => merge::Normalization::Average,
+            _ => merge::Normalization::Rays,
+        };
+        merge::merge_files(&files[1..], files[0], normalization).unwrap_or_else(fail);
+        return;
+    }
 
-pub const CHANNEL_BOUND: usize = 65536;
+    let path = matches.value_of("scene").unwrap();
+    let (scene, dir) = scene::SceneDescription::load(path).unwrap_or_else(fail);
+    let description = scene.build(&dir).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+    let names = description.names.clone();
 
-fn main() {
-    let collisions = world::basic_collisions();//absurd_collisions(500, [128.0, 128.0, 128.0]);
+    if let Some(file) = matches.value_of("export") {
+        let res = if file.ends_with(".obj") {
+            export::write_obj(&description, file)
+        } else {
+            export::write_gltf(&description, file)
+        };
+        res.unwrap_or_else(|e| fail(format!("{}: {}", file, e)));
+        return;
+    }
 
-    // Battements
-    /*let description = antennas::WorldDescriptor {
-        emitters: vec![
-            None,
-            Some(antennas::SignalEmitter {
-                position: Point3::new(6.0, 0.0, 0.0),
-                max_power: 1.0,
-                kind: simulation::EmissionKind::Pulse(1000000000.0),
-            }),
-            Some(antennas::SignalEmitter {
-                position: Point3::new(-8.0, 0.0, 0.0),
-                max_power: 1.0,
-                kind: simulation::EmissionKind::Pulse(1100000000.0),
-            }),
-        ],
-        receivers: vec![
-            Some(antennas::SignalReceiver {
-                position: Point3::new(0.0, 0.0, 0.0),
-                transfers: vec![vec![], vec![], vec![]],
-                kind: simulation::ReceptionKind::None,
-            }),
-            None,
-            None,
-        ],
-        names: vec!["first".into(), "second".into(), "third".into()],
-        collisions,
-    };*/
+    // The solve consumes the obstacles, the view keeps its own copy
+    let mut view = matches.value_of("svg").map(|_| {
+        scene.build(&dir).unwrap_or_else(|e| fail(format!("{}: {}", path, e)))
+    });
 
-    // OFDM
-    
-    /*let description = antennas::WorldDescriptor {
-        emitters: vec![
-            None,
-            Some(antennas::SignalEmitter {
-                position: Point3::new(-5.0, 0.0, 0.0),
-                max_power: 10.0,
-                kind: simulation::EmissionKind::OFDM(vec![0xBE, 0xEF, 0xCA, 0xFE]),
-            }),
-        ],
-        receivers: vec![
-            Some(antennas::SignalReceiver {
-                position: Point3::new(5.0, 0.0, 0.0),
-                transfers: vec![vec![], vec![]],
-                kind: simulation::ReceptionKind::OFDM,
-            }),
-            None,
-        ],
-        names: vec!["ofdm_rec".into(), "ofdm_emit".into()],
-        collisions,
-    };*/
+    let mut config = scene.tracer.clone();
+    if let Some(paths) = matches.value_of("paths") {
+        config.paths = paths
+            .parse()
+            .unwrap_or_else(|_| fail(format!("--paths expects a number, got {}", paths)));
+    }
+    let number = |name: &str, value: &str| {
+        value
+            .parse::<u64>()
+            .unwrap_or_else(|_| fail(format!("--{} expects a number, got {}", name, value)))
+    };
+    if let Some(seed) = matches.value_of("seed") {
+        config.seed = Some(number("seed", seed));
+    }
+    if matches.is_present("emitters") || matches.is_present("rays") {
+        let part = config.part.get_or_insert_with(waves::Part::default);
+        if let Some(emitters) = matches.values_of("emitters") {
+            part.emitters = emitters.map(str::to_owned).collect();
+        }
+        if let Some(mut rays) = matches.values_of("rays") {
+            let start = number("rays", rays.next().unwrap());
+            let end = number("rays", rays.next().unwrap());
+            part.rays = Some((start, end));
+        }
+        if !matches.is_present("save") {
+            return fail("a partial solve (--emitters, --rays) is only useful with --save");
+        }
+    }
 
-    // Moving
-    let (receivers, emitters, names) = world::moving_antennas();
-    let description = antennas::WorldDescriptor {
-        emitters,
-        receivers,
-        names,
-        collisions,
+    let ratio = |name: &str, value: &str| {
+        value
+            .parse::<f32>()
+            .unwrap_or_else(|_| fail(format!("--{} expects a number, got {}", name, value)))
+    };
+    let pruning = matches
+        .value_of("prune-threshold")
+        .map(|db| pruning::Pruning::Threshold(ratio("prune-threshold", db)))
+        .or_else(|| {
+            let fraction = matches.value_of("prune-energy")?;
+            Some(pruning::Pruning::Energy(ratio("prune-energy", fraction)))
+        })
+        .or_else(|| {
+            let beats = matches.value_of("prune-cluster")?;
+            Some(pruning::Pruning::Cluster(number("prune-cluster", beats) as usize))
+        });
+    let prune = |sim: &mut simulation::Simulation| {
+        if let Some(pruning) = pruning {
+            println!("Pruning taps: {}", sim.prune(pruning));
+        }
     };
 
-    let mut sim = simulation::Simulation::new(description);
+    if let Some(file) = matches.value_of("coverage") {
+        let values = |name: &str| -> Vec<f32> {
+            matches.values_of(name).unwrap().map(|x| ratio(name, x)).collect()
+        };
+        let origin = values("grid-origin");
+        let dims: Vec<usize> = matches
+            .values_of("grid-dims")
+            .unwrap()
+            .map(|x| match x.parse() {
+                Ok(0) | Err(_) => fail(format!("--grid-dims expects positive integers, got {}", x)),
+                Ok(n) => n,
+            })
+            .collect();
+        let grid = coverage::CoverageGrid {
+            origin: nalgebra::Point3::new(origin[0], origin[1], origin[2]),
+            step: ratio("grid-step", matches.value_of("grid-step").unwrap()),
+            dims: [dims[0], dims[1], dims[2]],
+        };
+        let layer = number("layer", matches.value_of("layer").unwrap()) as usize;
+        if layer >= grid.dims[1] {
+            let layers = grid.dims[1];
+            return fail(format!("--layer {} is out of the {} layers of the grid", layer, layers));
+        }
+        println!("Tracing coverage of {} cells...", grid.len());
+        let emitters = description.emitters.iter().filter_map(|x| x.clone()).collect();
+        let map = coverage::coverage(grid, emitters, description.collisions, &config);
+        let res = if file.ends_with(".pgm") {
+            map.write_pgm(file, layer)
+        } else if file.ends_with(".ppm") {
+            map.write_ppm(file, layer)
+        } else {
+            map.write_csv(file)
+        };
+        return res.unwrap_or_else(|e| fail(format!("{}: {}", file, e)));
+    }
 
-    println!("Solving...");
-    let time = chrono::Duration::span(|| sim.solve());
-    println!("Solved in {} seconds", time.num_seconds());
-    //println!("Saving solution...");
-    //sim.save_solution("output/solution.ron");
-    //let mut sim = simulation::Simulation::from_solution("output/solution.ron");
+    // Scenes with moving objects are traced at each instant of their timeline
+    let times = scene.times();
+
+    let mut sim = if let Some(model) = matches.value_of("channel") {
+        let taps = number("taps", matches.value_of("taps").unwrap()) as usize;
+        let model = match model {
+            "tdl-a" => channels::ChannelModel::TdlA,
+            "tdl-b" => channels::ChannelModel::TdlB,
+            "tdl-c" => channels::ChannelModel::TdlC,
+            "exponential" => channels::ChannelModel::Exponential(taps),
+            "rayleigh" => channels::ChannelModel::Rayleigh(taps),
+            _ => channels::ChannelModel::Rician {
+                taps,
+                k_factor: ratio("k-factor", matches.value_of("k-factor").unwrap()),
+            },
+        };
+        let channel = channels::SyntheticChannel {
+            model,
+            delay_spread: ratio("delay-spread", matches.value_of("delay-spread").unwrap()) * 1e-9,
+            seed: config.seed.unwrap_or(0),
+            path_loss: ratio("path-loss", matches.value_of("path-loss").unwrap()),
+        };
+        let mut sim = simulation::Simulation::new(description);
+        sim.config = config;
+        channels::apply(&mut sim.descriptor, &channel);
+        prune(&mut sim);
+        if let Some(file) = matches.value_of("save") {
+            println!("Saving solution...");
+            sim.try_save_solution(file).unwrap_or_else(fail);
+        }
+        sim
+    } else if let Some(file) = matches.value_of("load") {
+        println!("Loading solution...");
+        let expected = fingerprint::description_hash(&scene, &dir, &config)
+            .unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+        let mut sim =
+            simulation::Simulation::try_load_solution(file, expected).unwrap_or_else(fail);
+        sim.config = config;
+        prune(&mut sim);
+        sim
+    } else {
+        let mut sim = simulation::Simulation::new(description);
+        sim.config = config;
+        println!("Solving {} frames...", times.len().max(1));
+        let start = chrono::Utc::now();
+        let solved = if times.is_empty() {
+            sim.try_solve()
+        } else {
+            sim.try_solve_timeline(&times, |time| Ok(scene.build_at(&dir, time)?))
+        };
+        solved.unwrap_or_else(fail);
+        println!("Solved in {} seconds", (chrono::Utc::now() - start).num_seconds());
+        prune(&mut sim);
+        if let Some(file) = matches.value_of("save") {
+            println!("Saving solution...");
+            sim.try_save_solution(file).unwrap_or_else(fail);
+        }
+        sim
+    };
+    for issue in &sim.warnings {
+        eprintln!("{}", issue);
+    }
+    if let Some(mut link) = matches.values_of("response") {
+        let receiver = link.next().unwrap();
+        let emitter = link.next().unwrap();
+        let file = link.next().unwrap();
+        let taps = sim.transfer(receiver, emitter).unwrap_or_else(|| {
+            fail(format!("no transfer function from {} to {}", emitter, receiver))
+        });
+        let mut band = response::Band::default();
+        if let Some(mut values) = matches.values_of("band") {
+            band.start = ratio("band", values.next().unwrap());
+            band.end = ratio("band", values.next().unwrap());
+        }
+        if let Some(resolution) = matches.value_of("resolution") {
+            band.resolution = ratio("resolution", resolution);
+        }
+        let h = response::frequency_response(taps, band).unwrap_or_else(fail);
+        h.write(file).unwrap_or_else(|e| fail(format!("{}: {}", file, e)));
+        // output/link.csv gives output/link.pdp.csv
+        let pdp = match file.rfind('.') {
+            Some(dot) if !file[dot..].contains('/') => {
+                format!("{}.pdp{}", &file[..dot], &file[dot..])
+            }
+            _ => format!("{}.pdp", file),
+        };
+        response::write_power_delay_profile(taps, &pdp)
+            .unwrap_or_else(|e| fail(format!("{}: {}", pdp, e)));
+        return;
+    }
+    if matches.is_present("statistics") {
+        let threshold = ratio("excess-threshold", matches.value_of("excess-threshold").unwrap());
+        let links = sim.statistics(threshold);
+        print!("{}", statistics::Table(&links));
+        if let Some(file) = matches.value_of("statistics") {
+            statistics::write_csv(&links, file)
+                .unwrap_or_else(|e| fail(format!("{}: {}", file, e)));
+        }
+        return;
+    }
+    // Partial solutions are merged before being run
+    if sim.config.part.is_some() {
+        return;
+    }
+    if let (Some(view), Some(file)) = (view.as_mut(), matches.value_of("svg")) {
+        view.receivers = sim.descriptor.receivers.clone();
+        svg::write_svg(view, file, sim.config.paths)
+            .unwrap_or_else(|e| fail(format!("{}: {}", file, e)));
+    }
     println!("Instanciating solution...");
-    sim.instanciate();
+    sim.try_instanciate().unwrap_or_else(fail);
     println!("Running...");
-    let time =
-        chrono::Duration::span(|| sim.start(vec!["ofdm_rec".into(), "ofdm_emit".into()], 0x20000));
-    println!("Ran in {} seconds", time.num_seconds());
+    let start = chrono::Utc::now();
+    sim.try_start(names, 0x20000).unwrap_or_else(fail);
+    println!("Ran in {} seconds", (chrono::Utc::now() - start).num_seconds());
     println!("Gathering OFDM results...");
     sim.world.exec(
         |(recs, or): (
@@ -0,0 +1,193 @@
+// materials.rs
+//! Electromagnetic properties of obstacles. The built-in library follows
+//! ITU-R P.2040 (building materials and ground) and the double Debye model
+//! of ITU-R P.527 for water, evaluated at `MAX_FREQUENCY`.
+
+use crate::{MAX_FREQUENCY, WAVE_VELOCITY};
+
+use rustfft::num_complex::Complex;
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+/// Refractive index of air
+pub const AIR_INDEX: f32 = 1.0;
+const VACUUM_PERMITTIVITY: f32 = 8.854_188e-12;
+
+/// Materials by name
+pub type MaterialLibrary = HashMap<String, Material>;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Material {
+    /// Real part of the relative permittivity
+    pub permittivity: f32,
+    /// Conductivity in S/m
+    pub conductivity: f32,
+    /// Power attenuation inside the material in Np/m, derived from the
+    /// conductivity when absent
+    pub absorbance: Option<f32>,
+    /// Standard deviation of the surface height in meters
+    pub roughness: f32,
+    /// Usual thickness of thin layers (panes, boards), None for bulk materials
+    pub thickness: Option<f32>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            permittivity: 1.0,
+            conductivity: 0.0,
+            absorbance: None,
+            roughness: 0.0,
+            thickness: None,
+        }
+    }
+}
+
+impl Material {
+    /// Lossless material of refractive index `n`
+    pub fn dielectric(n: f32) -> Self {
+        Self {
+            permittivity: n * n,
+            ..Self::default()
+        }
+    }
+
+    /// Model of ITU-R P.2040 table 3: permittivity a.f^b and conductivity c.f^d, f in GHz.
+    /// The table gives each model up to `max` GHz, above it the model is held at `max`
+    /// rather than extrapolated.
+    fn itu(a: f32, b: f32, c: f32, d: f32, max: f32) -> Self {
+        let f = (MAX_FREQUENCY / 1e9).min(max);
+        Self {
+            permittivity: a * f.powf(b),
+            conductivity: c * f.powf(d),
+            ..Self::default()
+        }
+    }
+
+    pub fn concrete() -> Self {
+        Self::itu(5.24, 0.0, 0.0462, 0.7822, 100.0)
+    }
+
+    /// Valid up to 10 GHz, so approximate at millimeter waves
+    pub fn brick() -> Self {
+        Self::itu(3.91, 0.0, 0.0238, 0.16, 10.0)
+    }
+
+    pub fn glass() -> Self {
+        Self {
+            thickness: Some(0.006),
+            ..Self::itu(6.31, 0.0, 0.0036, 1.3394, 100.0)
+        }
+    }
+
+    /// Plasterboard
+    pub fn drywall() -> Self {
+        Self {
+            thickness: Some(0.0125),
+            ..Self::itu(2.73, 0.0, 0.0085, 0.9395, 100.0)
+        }
+    }
+
+    pub fn wood() -> Self {
+        Self::itu(1.99, 0.0, 0.0047, 1.0718, 100.0)
+    }
+
+    pub fn metal() -> Self {
+        Self::itu(1.0, 0.0, 1e7, 0.0, 100.0)
+    }
+
+    /// Medium dry ground, valid up to 10 GHz so approximate at millimeter waves
+    pub fn soil() -> Self {
+        Self::itu(15.0, -0.1, 0.035, 1.63, 10.0)
+    }
+
+    /// Fresh water at 20 °C
+    pub fn water() -> Self {
+        let theta = 300.0 / 293.15 - 1.0;
+        let static_permittivity = 77.66 + 103.3 * theta;
+        let high = 0.0671 * static_permittivity;
+        let optical = 3.52;
+        let f1 = 20.2 - 146.0 * theta + 316.0 * theta * theta;
+        let f = MAX_FREQUENCY / 1e9;
+
+        let permittivity = (static_permittivity - high) / Complex::new(1.0, f / f1)
+            + (high - optical) / Complex::new(1.0, f / (39.8 * f1))
+            + optical;
+        Self {
+            permittivity: permittivity.re,
+            conductivity: -permittivity.im * 2.0 * PI * MAX_FREQUENCY * VACUUM_PERMITTIVITY,
+            ..Self::default()
+        }
+    }
+
+    /// Complex relative permittivity at `MAX_FREQUENCY`
+    fn complex_permittivity(&self) -> Complex<f32> {
+        let omega = 2.0 * PI * MAX_FREQUENCY;
+        Complex::new(
+            self.permittivity,
+            -self.conductivity / (omega * VACUUM_PERMITTIVITY),
+        )
+    }
+
+    /// Real part of the complex refractive index
+    pub fn refractive_index(&self) -> f32 {
+        self.complex_permittivity().sqrt().re
+    }
+
+    /// Power attenuation inside the material in Np/m
+    pub fn absorbance(&self) -> f32 {
+        self.absorbance.unwrap_or_else(|| {
+            let k = 2.0 * PI * MAX_FREQUENCY / WAVE_VELOCITY;
+            2.0 * k * self.complex_permittivity().sqrt().im.abs()
+        })
+    }
+
+    /// Fraction of the reflected amplitude kept in the specular direction
+    /// (Rayleigh criterion), `cos_incidence` measured from the normal
+    pub fn specular_factor(&self, cos_incidence: f32) -> f32 {
+        if self.roughness <= 0.0 {
+            return 1.0;
+        }
+        let wavelength = WAVE_VELOCITY / MAX_FREQUENCY;
+        let x = PI * self.roughness * cos_incidence / wavelength;
+        (-8.0 * x * x).exp()
+    }
+
+    /// Description of the first invalid value, if any. The negated comparisons
+    /// also catch NaN.
+    #[allow(clippy::neg_cmp_op_on_partial_ord)]
+    pub fn check(&self) -> Option<&'static str> {
+        if !(self.permittivity >= 1.0) {
+            Some("permittivity must be at least 1")
+        } else if !(self.conductivity >= 0.0) {
+            Some("conductivity must not be negative")
+        } else if self.absorbance.is_some_and(|x| !(x >= 0.0)) {
+            Some("absorbance must not be negative")
+        } else if !(self.roughness >= 0.0) {
+            Some("roughness must not be negative")
+        } else if self.thickness.is_some_and(|x| !(x > 0.0)) {
+            Some("thickness must be positive")
+        } else {
+            None
+        }
+    }
+}
+
+/// Built-in materials
+pub fn library() -> MaterialLibrary {
+    vec![
+        ("concrete", Material::concrete()),
+        ("brick", Material::brick()),
+        ("glass", Material::glass()),
+        ("drywall", Material::drywall()),
+        ("wood", Material::wood()),
+        ("metal", Material::metal()),
+        ("soil", Material::soil()),
+        ("water", Material::water()),
+    ]
+    .into_iter()
+    .map(|(name, material)| (name.to_string(), material))
+    .collect()
+}
@@ -0,0 +1,315 @@
+// merge.rs
+//! Combine the partial solutions of a solve split into jobs, each tracing
+//! some emitters or some rays, see `waves::Part`.
+
+use crate::antennas::{SerializableWorld, SignalEvent, SignalReceiver};
+use crate::error::{Error, Result};
+use crate::solution::{self, Metadata};
+use crate::waves::{TracerReport, RAYS_PER_EMITTER};
+use crate::TIME_PER_BEAT;
+
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Normalization {
+    /// Scale each emitter by the rays of a full solve over the rays traced
+    /// by all the parts: parts tracing different rays are summed, repeats
+    /// of the same rays with other seeds are averaged. Exact when the parts
+    /// cover every ray the same number of times.
+    Rays,
+    Sum,
+    Average,
+}
+
+fn incompatible(path: &str, message: impl ToString) -> Error {
+    Error::Merge {
+        path: path.to_owned(),
+        message: message.to_string(),
+    }
+}
+
+/// Sum of the taps of the parts at each time, scaled
+fn combine<'a>(
+    parts: impl Iterator<Item = &'a Vec<SignalEvent>>,
+    scale: f32,
+) -> Vec<SignalEvent> {
+    let mut taps = BTreeMap::new();
+    for part in parts {
+        for tap in part {
+            *taps.entry(tap.time).or_insert(0.0) += tap.gain;
+        }
+    }
+    taps.into_iter()
+        .map(|(time, gain)| SignalEvent {
+            time,
+            gain: gain * scale,
+        })
+        .collect()
+}
+
+/// Merge partial solutions of the same scene, given with their paths for errors
+pub fn merge<'a>(
+    parts: &'a [(String, SerializableWorld)],
+    normalization: Normalization,
+) -> Result<SerializableWorld> {
+    let (first_path, first) = parts
+        .first()
+        .ok_or_else(|| incompatible("", "no solution to merge"))?;
+    for (path, part) in parts {
+        if part.names != first.names {
+            return Err(incompatible(path, format!("antennas differ from {}", first_path)));
+        }
+        if part.scene_hash != first.scene_hash || part.timeline != first.timeline {
+            return Err(incompatible(path, format!("scene differs from {}", first_path)));
+        }
+        let shape = |x: &SignalReceiver| {
+            let frames: Vec<usize> = x.frames.iter().map(Vec::len).collect();
+            (x.transfers.len(), frames)
+        };
+        let receivers = part.receivers.iter().zip(&first.receivers);
+        if receivers.clone().any(|(a, b)| a.as_ref().map(shape) != b.as_ref().map(shape)) {
+            return Err(incompatible(path, format!("receivers differ from {}", first_path)));
+        }
+    }
+
+    // Scale of each emitter at each frame
+    let antennas = first.names.len();
+    let frames = first.timeline.len().max(1);
+    let mut scales = vec![vec![1.0f32; antennas]; frames];
+    match normalization {
+        Normalization::Sum => (),
+        Normalization::Average => {
+            for scale in scales.iter_mut().flatten() {
+                *scale = 1.0 / parts.len() as f32;
+            }
+        }
+        Normalization::Rays => {
+            let mut traced = vec![vec![0usize; antennas]; frames];
+            for (path, part) in parts {
+                let reports = part
+                    .metadata
+                    .as_ref()
+                    .map(|x| &x.reports[..])
+                    .filter(|x| {
+                        x.len() == frames && x.iter().all(|x| x.emitter_rays.len() == antennas)
+                    })
+                    .ok_or_else(|| incompatible(path, "no ray counts, merge with Sum or Average"))?;
+                for (f, report) in reports.iter().enumerate() {
+                    for (e, &rays) in report.emitter_rays.iter().enumerate() {
+                        traced[f][e] += rays;
+                    }
+                }
+            }
+            let scale = |rays: usize| {
+                if rays > 0 {
+                    RAYS_PER_EMITTER as f32 / rays as f32
+                } else {
+                    0.0
+                }
+            };
+            scales = traced
+                .iter()
+                .map(|x| x.iter().map(|&rays| scale(rays)).collect())
+                .collect();
+        }
+    }
+
+    let receivers = (0..antennas)
+        .map(|i| {
+            first.receivers[i].as_ref().map(|receiver| {
+                // Checked above, every part has this receiver
+                let of = |p: &'a (String, SerializableWorld)| -> &'a SignalReceiver {
+                    p.1.receivers[i].as_ref().unwrap()
+                };
+                let transfers = (0..receiver.transfers.len())
+                    .map(|e| combine(parts.iter().map(|p| &of(p).transfers[e]), scales[0][e]))
+                    .collect();
+                let frames = (0..receiver.frames.len())
+                    .map(|f| {
+                        (0..receiver.frames[f].len())
+                            .map(|e| {
+                                combine(parts.iter().map(|p| &of(p).frames[f][e]), scales[f][e])
+                            })
+                            .collect()
+                    })
+                    .collect();
+                let paths = (0..receiver.paths.len())
+                    .map(|e| {
+                        let count = parts
+                            .iter()
+                            .map(|p| of(p).paths.get(e).map_or(0, Vec::len))
+                            .max();
+                        let mut paths: Vec<_> = parts
+                            .iter()
+                            .flat_map(|p| of(p).paths.get(e).cloned().unwrap_or_default())
+                            .collect();
+                        paths.sort_by(|a, b| b.gain.abs().partial_cmp(&a.gain.abs()).unwrap());
+                        paths.truncate(count.unwrap_or(0));
+                        paths
+                    })
+                    .collect();
+                SignalReceiver {
+                    position: receiver.position,
+                    transfers,
+                    paths,
+                    frames,
+                    kind: receiver.kind.clone(),
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // The metadata of the parts are summed up, when they all have one
+    let metadata: Option<Vec<&Metadata>> = parts.iter().map(|p| p.1.metadata.as_ref()).collect();
+    let metadata = metadata.map(|all| {
+        let reports = (0..frames)
+            .map(|f| {
+                let sum = |get: &dyn Fn(&TracerReport) -> Vec<f32>| {
+                    let mut sum = vec![0.0f32; antennas];
+                    for report in all.iter().filter_map(|x| x.reports.get(f)) {
+                        for (s, x) in sum.iter_mut().zip(get(report)) {
+                            *s += x;
+                        }
+                    }
+                    sum
+                };
+                let rays = sum(&|x| x.emitter_rays.iter().map(|&x| x as f32).collect());
+                let emitted = sum(&|x| x.emitted.clone());
+                TracerReport {
+                    seed: all[0].reports.get(f).map_or(0, |x| x.seed),
+                    rays: rays.iter().sum::<f32>() as usize,
+                    emitter_rays: rays.iter().map(|&x| x as usize).collect(),
+                    emitted: emitted.iter().zip(&scales[f]).map(|(x, s)| x * s).collect(),
+                    received: receivers
+                        .iter()
+                        .map(|x| {
+                            x.as_ref().map_or(0.0, |x| {
+                                let transfers = x.frames.get(f).unwrap_or(&x.transfers);
+                                transfers.iter().flatten().map(|e| e.gain.abs()).sum()
+                            })
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        let mut tracer = all[0].tracer.clone();
+        tracer.part = None;
+        Metadata {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            created: chrono::Utc::now().to_rfc3339(),
+            tracer,
+            sample_rate: 1.0 / TIME_PER_BEAT,
+            duration: all.iter().map(|x| x.duration).sum(),
+            reports,
+        }
+    });
+
+    Ok(SerializableWorld {
+        emitters: first.emitters.clone(),
+        receivers,
+        names: first.names.clone(),
+        scene_hash: first.scene_hash,
+        timeline: first.timeline.clone(),
+        metadata,
+    })
+}
+
+/// Merge the solution files `inputs` into `output`
+pub fn merge_files(inputs: &[&str], output: &str, normalization: Normalization) -> Result<()> {
+    let parts = inputs
+        .iter()
+        .map(|&path| {
+            let part = solution::load(path).map_err(|e| Error::io(path, e))?;
+            Ok((path.to_owned(), part))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let merged = merge(&parts, normalization)?;
+    solution::save(&merged, output).map_err(|e| Error::io(output, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::antennas::SignalEmitter;
+    use crate::simulation::{EmissionKind, ReceptionKind};
+    use crate::waves::TracerConfig;
+    use nalgebra::Point3;
+
+    /// Part of a solve of a receiver and an emitter, `rays` of the emitter traced
+    fn part(taps: &[(usize, f32)], rays: usize) -> (String, SerializableWorld) {
+        let transfer = taps.iter().map(|&(time, gain)| SignalEvent { time, gain }).collect();
+        let receiver = SignalReceiver {
+            position: Point3::origin(),
+            transfers: vec![Vec::new(), transfer],
+            paths: Vec::new(),
+            frames: Vec::new(),
+            kind: ReceptionKind::None,
+        };
+        let emitter = SignalEmitter {
+            position: Point3::new(1.0, 0.0, 0.0),
+            max_power: 1.0,
+            kind: EmissionKind::Pulse(1.0),
+        };
+        let report = TracerReport {
+            seed: 0,
+            rays,
+            emitter_rays: vec![0, rays],
+            emitted: vec![0.0, rays as f32],
+            received: vec![0.0, 0.0],
+        };
+        let world = SerializableWorld {
+            emitters: vec![None, Some(emitter)],
+            receivers: vec![Some(receiver), None],
+            names: vec!["rec".to_owned(), "emit".to_owned()],
+            scene_hash: Some(1),
+            timeline: Vec::new(),
+            metadata: Some(Metadata::new(
+                &TracerConfig::default(),
+                chrono::Duration::seconds(1),
+                vec![report],
+            )),
+        };
+        (format!("part{}", rays), world)
+    }
+
+    fn transfer(world: &SerializableWorld) -> Vec<(usize, f32)> {
+        let receiver = world.receivers[0].as_ref().unwrap();
+        receiver.transfers[1].iter().map(|x| (x.time, x.gain)).collect()
+    }
+
+    #[test]
+    fn normalizations() {
+        let half = RAYS_PER_EMITTER / 2;
+        let parts = vec![part(&[(1, 1.0), (3, 2.0)], half), part(&[(3, 4.0)], half)];
+        let merged = |normalization| merge(&parts, normalization).map(|x| transfer(&x)).unwrap();
+        assert_eq!(merged(Normalization::Sum), [(1, 1.0), (3, 6.0)]);
+        assert_eq!(merged(Normalization::Average), [(1, 0.5), (3, 3.0)]);
+        // Two halves of the rays add up
+        assert_eq!(merged(Normalization::Rays), [(1, 1.0), (3, 6.0)]);
+
+        // Two full solves are averaged
+        let parts = vec![part(&[(3, 2.0)], RAYS_PER_EMITTER), part(&[(3, 4.0)], RAYS_PER_EMITTER)];
+        let merged = merge(&parts, Normalization::Rays).unwrap();
+        assert_eq!(transfer(&merged), [(3, 3.0)]);
+        let report = &merged.metadata.unwrap().reports[0];
+        assert_eq!(report.emitter_rays, [0, 2 * RAYS_PER_EMITTER]);
+        assert_eq!(report.received, [3.0, 0.0]);
+    }
+
+    #[test]
+    fn rays_need_metadata() {
+        let mut parts = vec![part(&[(3, 2.0)], 10)];
+        parts[0].1.metadata = None;
+        assert!(merge(&parts, Normalization::Rays).is_err());
+        assert!(merge(&parts, Normalization::Sum).is_ok());
+    }
+
+    #[test]
+    fn other_scene() {
+        let mut parts = vec![part(&[(3, 2.0)], 10), part(&[(3, 2.0)], 10)];
+        parts[1].1.scene_hash = Some(2);
+        assert!(merge(&parts, Normalization::Sum).is_err());
+        assert!(merge(&[], Normalization::Sum).is_err());
+    }
+}
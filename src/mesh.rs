@@ -0,0 +1,275 @@
+// mesh.rs
+//! Triangle meshes imported from CAD models (Wavefront OBJ and STL).
+
+use crate::antennas::{create_bvt_tuple, SceneObject};
+use crate::error::invalid;
+use crate::materials::Material;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use nalgebra::{Isometry3, Point3};
+use ncollide3d::bounding_volume::aabb::AABB;
+use ncollide3d::shape::TriMesh;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read};
+
+fn mesh_object(
+    points: Vec<Point3<f32>>,
+    indices: Vec<Point3<usize>>,
+    transform: Isometry3<f32>,
+    material: Material,
+) -> (SceneObject, AABB<f32>) {
+    create_bvt_tuple(&TriMesh::new(points, indices, None), transform, material)
+}
+
+/// Load a Wavefront OBJ file, one `SceneObject` per material.
+/// Faces are matched by their `usemtl` name, or by their group name when no
+/// material is set, and fall back to `default` when the name is not in `materials`.
+pub fn load_obj(
+    path: &str,
+    transform: Isometry3<f32>,
+    materials: &HashMap<String, Material>,
+    default: Material,
+) -> io::Result<Vec<(SceneObject, AABB<f32>)>> {
+    let file = BufReader::new(std::fs::File::open(path)?);
+
+    let mut vertices: Vec<Point3<f32>> = Vec::new();
+    // By name, in the order they first appear so that the scene hash is stable
+    let mut faces: Vec<(Option<String>, Vec<[usize; 3]>)> = Vec::new();
+    let mut group = None;
+    let mut material = None;
+
+    for (i, line) in file.lines().enumerate() {
+        let line = line?;
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => {
+                let coords = words
+                    .take(3)
+                    .map(|x| x.parse::<f32>().map_err(|e| invalid(path, i, e)))
+                    .collect::<io::Result<Vec<_>>>()?;
+                if coords.len() != 3 {
+                    return Err(invalid(path, i, "vertex with less than 3 coordinates"));
+                }
+                vertices.push(Point3::new(coords[0], coords[1], coords[2]));
+            }
+            Some("f") => {
+                let face = words
+                    .map(|x| {
+                        // v, v/vt, v//vn or v/vt/vn, indices start at 1 or count from the end
+                        let index: isize = x
+                            .split('/')
+                            .next()
+                            .unwrap_or("")
+                            .parse()
+                            .map_err(|e| invalid(path, i, e))?;
+                        let index = if index < 0 {
+                            vertices.len() as isize + index
+                        } else {
+                            index - 1
+                        };
+                        if index < 0 || index as usize >= vertices.len() {
+                            return Err(invalid(path, i, "vertex index out of bounds"));
+                        }
+                        Ok(index as usize)
+                    })
+                    .collect::<io::Result<Vec<_>>>()?;
+                if face.len() < 3 {
+                    return Err(invalid(path, i, "face with less than 3 vertices"));
+                }
+
+                let key = material.clone().or_else(|| group.clone());
+                let triangles = match faces.iter().position(|x| x.0 == key) {
+                    Some(k) => &mut faces[k].1,
+                    None => {
+                        faces.push((key, Vec::new()));
+                        &mut faces.last_mut().unwrap().1
+                    }
+                };
+                // Polygons are assumed convex
+                for k in 1..face.len() - 1 {
+                    triangles.push([face[0], face[k], face[k + 1]]);
+                }
+            }
+            Some("g") | Some("o") => group = words.next().map(|x| x.to_owned()),
+            Some("usemtl") => material = words.next().map(|x| x.to_owned()),
+            _ => (),
+        }
+    }
+
+    Ok(faces
+        .into_iter()
+        .map(|(name, triangles)| {
+            // Only keep the vertices used by this material
+            let mut remap = HashMap::new();
+            let mut points = Vec::new();
+            let indices = triangles
+                .iter()
+                .map(|tri| {
+                    let mut id = |v: usize| {
+                        *remap.entry(v).or_insert_with(|| {
+                            points.push(vertices[v]);
+                            points.len() - 1
+                        })
+                    };
+                    Point3::new(id(tri[0]), id(tri[1]), id(tri[2]))
+                })
+                .collect();
+
+            let material = name
+                .and_then(|name| materials.get(&name))
+                .cloned()
+                .unwrap_or_else(|| default.clone());
+            mesh_object(points, indices, transform, material)
+        })
+        .collect())
+}
+
+/// Load a binary or ASCII STL file as a single `SceneObject`
+pub fn load_stl(
+    path: &str,
+    transform: Isometry3<f32>,
+    material: Material,
+) -> io::Result<(SceneObject, AABB<f32>)> {
+    let data = std::fs::read(path)?;
+
+    let binary_len = |data: &[u8]| {
+        data.get(80..84)
+            .map(|mut x| x.read_u32::<LittleEndian>().unwrap() as usize * 50 + 84)
+    };
+    // Some binary files also start with "solid", the size tells them apart
+    let points = if data.starts_with(b"solid") && binary_len(&data) != Some(data.len()) {
+        read_ascii_stl(path, &data)?
+    } else {
+        read_binary_stl(path, &data)?
+    };
+
+    let indices = (0..points.len() / 3)
+        .map(|i| Point3::new(3 * i, 3 * i + 1, 3 * i + 2))
+        .collect();
+    Ok(mesh_object(points, indices, transform, material))
+}
+
+fn read_binary_stl(path: &str, data: &[u8]) -> io::Result<Vec<Point3<f32>>> {
+    if data.len() < 84 {
+        return Err(invalid(path, 0, "truncated STL header"));
+    }
+
+    let mut reader = &data[80..];
+    let count = reader.read_u32::<LittleEndian>()? as usize;
+    let mut points = Vec::with_capacity(count * 3);
+    let mut triangle = [0.0f32; 12];
+    for _ in 0..count {
+        // Normal then the 3 vertices, the normal is recomputed from the winding
+        reader.read_f32_into::<LittleEndian>(&mut triangle)?;
+        for v in 1..4 {
+            points.push(Point3::new(
+                triangle[3 * v],
+                triangle[3 * v + 1],
+                triangle[3 * v + 2],
+            ));
+        }
+        reader.read_u16::<LittleEndian>()?; // Attribute byte count
+    }
+    Ok(points)
+}
+
+fn read_ascii_stl(path: &str, mut data: &[u8]) -> io::Result<Vec<Point3<f32>>> {
+    let mut text = String::new();
+    data.read_to_string(&mut text)?;
+
+    let mut points = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        if words.next() == Some("vertex") {
+            let coords = words
+                .map(|x| x.parse::<f32>().map_err(|e| invalid(path, i, e)))
+                .collect::<io::Result<Vec<_>>>()?;
+            if coords.len() != 3 {
+                return Err(invalid(path, i, "vertex without 3 coordinates"));
+            }
+            points.push(Point3::new(coords[0], coords[1], coords[2]));
+        }
+    }
+
+    if points.len() % 3 != 0 {
+        return Err(invalid(path, 0, "facets must have 3 vertices"));
+    }
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempFile;
+    use byteorder::WriteBytesExt;
+
+    #[test]
+    fn obj_materials() {
+        let file = TempFile::with(
+            "model.obj",
+            b"v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 0 0 4\n\
+              g walls\nf 1/1 2/2 3/3 4/4\nusemtl glass\nf -1//1 1//1 2//1\n",
+        );
+        let mut materials = HashMap::new();
+        materials.insert("walls".to_owned(), Material::brick());
+        materials.insert("glass".to_owned(), Material::glass());
+        let objects =
+            load_obj(file.path(), Isometry3::identity(), &materials, Material::wood()).unwrap();
+
+        // In the order of the file
+        assert_eq!(objects.len(), 2);
+        assert_eq!(*objects[0].0.material(), Material::brick());
+        assert_eq!(objects[0].1.maxs(), &Point3::new(1.0, 1.0, 0.0));
+        assert_eq!(*objects[1].0.material(), Material::glass());
+        assert_eq!(objects[1].1.maxs(), &Point3::new(1.0, 0.0, 4.0));
+
+        // Unknown names fall back to the default
+        let none = HashMap::new();
+        let objects = load_obj(file.path(), Isometry3::identity(), &none, Material::wood());
+        assert!(objects.unwrap().iter().all(|x| *x.0.material() == Material::wood()));
+    }
+
+    #[test]
+    fn obj_errors() {
+        let none = HashMap::new();
+        let load = |name, content: &str| {
+            let file = TempFile::with(name, content.as_bytes());
+            load_obj(file.path(), Isometry3::identity(), &none, Material::wood()).err().unwrap()
+        };
+        assert!(load("bounds.obj", "v 0 0 0\nv 1 0 0\nf 1 2 3\n")
+            .to_string()
+            .ends_with(":3: vertex index out of bounds"));
+        assert!(load("short.obj", "v 0 0\n")
+            .to_string()
+            .ends_with(":1: vertex with less than 3 coordinates"));
+        assert!(load("line.obj", "v 0 0 0\nv 1 0 0\nf 1 2\n")
+            .to_string()
+            .ends_with(":3: face with less than 3 vertices"));
+    }
+
+    #[test]
+    fn stl() {
+        let ascii = b"solid t\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\n\
+                      vertex 0 1 0\nendloop\nendfacet\nendsolid t\n";
+        let points = read_ascii_stl("t.stl", ascii).unwrap();
+        let facet = [Point3::origin(), Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0)];
+        assert_eq!(points, facet);
+        assert!(read_ascii_stl("t.stl", b"solid t\nvertex 0 0 0\nendsolid t\n").is_err());
+
+        // The same facet in binary, with a header that starts with "solid" too
+        let mut binary = b"solid".to_vec();
+        binary.resize(80, 0);
+        binary.write_u32::<LittleEndian>(1).unwrap();
+        for x in &[0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            binary.write_f32::<LittleEndian>(*x).unwrap();
+        }
+        binary.write_u16::<LittleEndian>(0).unwrap();
+        assert_eq!(read_binary_stl("t.stl", &binary).unwrap(), points);
+        assert!(read_binary_stl("t.stl", &binary[..100]).is_err());
+
+        let file = TempFile::with("facet.stl", &binary);
+        let (_, aabb) = load_stl(file.path(), Isometry3::identity(), Material::metal()).unwrap();
+        assert_eq!(aabb.maxs(), &Point3::new(1.0, 1.0, 0.0));
+    }
+}
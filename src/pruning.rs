@@ -0,0 +1,284 @@
+// pruning.rs
+//! Remove the weakest taps of the transfer functions, so that the propagation
+//! has fewer events to go through at each beat.
+//!
+//! As in `coverage`, the power of a tap is the absolute value of its gain, the
+//! energy of the rays that arrived at that time. The error is measured the
+//! same way, on the difference of the transfer functions.
+
+use crate::antennas::{SignalEvent, SignalReceiver};
+
+use std::cmp::Ordering;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pruning {
+    /// Remove the taps whose power is that many dB below the strongest tap
+    Threshold(f32),
+    /// Keep the strongest taps holding this fraction of the power, in [0, 1]
+    Energy(f32),
+    /// Merge the taps less than this many beats apart into one tap, at the
+    /// time of the strongest with the sum of their gains
+    Cluster(usize),
+}
+
+/// Effect of a pruning on a set of transfer functions
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PruningReport {
+    pub taps: usize,
+    pub kept: usize,
+    /// Energy of the transfer functions before pruning
+    pub energy: f32,
+    /// Energy of the difference between the transfer functions before and after
+    pub error: f32,
+    /// Largest relative error of a single transfer function
+    pub max_error: f32,
+}
+
+impl PruningReport {
+    /// Error relative to the energy before pruning
+    pub fn relative_error(&self) -> f32 {
+        if self.energy > 0.0 {
+            self.error / self.energy
+        } else {
+            0.0
+        }
+    }
+
+    fn add(&mut self, other: &PruningReport) {
+        self.taps += other.taps;
+        self.kept += other.kept;
+        self.energy += other.energy;
+        self.error += other.error;
+        self.max_error = self.max_error.max(other.max_error);
+    }
+}
+
+impl fmt::Display for PruningReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let db = |x: f32| 10.0 * x.log10();
+        write!(
+            f,
+            "kept {} of {} taps, error {:.1} dB (worst link {:.1} dB)",
+            self.kept,
+            self.taps,
+            db(self.relative_error()),
+            db(self.max_error)
+        )
+    }
+}
+
+fn energy(taps: &[SignalEvent]) -> f32 {
+    taps.iter().map(|x| x.gain.abs()).sum()
+}
+
+/// Energy of `before - after`, both sorted by time
+fn residual(before: &[SignalEvent], after: &[SignalEvent]) -> f32 {
+    let (mut i, mut j) = (0, 0);
+    let mut error = 0.0;
+    loop {
+        let diff = match (before.get(i), after.get(j)) {
+            (Some(a), Some(b)) => match a.time.cmp(&b.time) {
+                Ordering::Equal => {
+                    i += 1;
+                    j += 1;
+                    a.gain - b.gain
+                }
+                Ordering::Less => {
+                    i += 1;
+                    a.gain
+                }
+                Ordering::Greater => {
+                    j += 1;
+                    -b.gain
+                }
+            },
+            (Some(a), None) => {
+                i += 1;
+                a.gain
+            }
+            (None, Some(b)) => {
+                j += 1;
+                -b.gain
+            }
+            (None, None) => return error,
+        };
+        error += diff.abs();
+    }
+}
+
+/// Pruned copy of a transfer function sorted by time, which stays sorted
+pub fn prune(taps: &[SignalEvent], pruning: Pruning) -> Vec<SignalEvent> {
+    match pruning {
+        Pruning::Threshold(db) => {
+            let strongest = taps.iter().map(|x| x.gain.abs()).fold(0.0, f32::max);
+            let threshold = strongest * 10f32.powf(-db / 10.0);
+            taps.iter()
+                .filter(|x| x.gain.abs() >= threshold)
+                .cloned()
+                .collect()
+        }
+        Pruning::Energy(fraction) => {
+            let mut order: Vec<usize> = (0..taps.len()).collect();
+            order.sort_by(|&a, &b| taps[b].gain.abs().partial_cmp(&taps[a].gain.abs()).unwrap());
+            let target = taps.iter().map(|x| x.gain.abs()).sum::<f32>() * fraction;
+            let mut kept = vec![false; taps.len()];
+            let mut sum = 0.0;
+            for i in order {
+                if sum >= target {
+                    break;
+                }
+                sum += taps[i].gain.abs();
+                kept[i] = true;
+            }
+            taps.iter()
+                .zip(kept)
+                .filter(|x| x.1)
+                .map(|x| x.0.clone())
+                .collect()
+        }
+        Pruning::Cluster(window) => {
+            let mut clusters: Vec<SignalEvent> = Vec::new();
+            // Start and strongest gain of the current cluster
+            let mut start = 0;
+            let mut strongest = 0.0f32;
+            for tap in taps {
+                match clusters.last_mut() {
+                    Some(last) if tap.time - start < window => {
+                        if tap.gain.abs() > strongest {
+                            strongest = tap.gain.abs();
+                            last.time = tap.time;
+                        }
+                        last.gain += tap.gain;
+                    }
+                    _ => {
+                        start = tap.time;
+                        strongest = tap.gain.abs();
+                        clusters.push(tap.clone());
+                    }
+                }
+            }
+            clusters
+        }
+    }
+}
+
+/// Prune a transfer function in place
+pub fn prune_transfer(taps: &mut Vec<SignalEvent>, pruning: Pruning) -> PruningReport {
+    let pruned = prune(taps, pruning);
+    let energy = energy(taps);
+    let error = residual(taps, &pruned);
+    let report = PruningReport {
+        taps: taps.len(),
+        kept: pruned.len(),
+        energy,
+        error,
+        max_error: if energy > 0.0 { error / energy } else { 0.0 },
+    };
+    *taps = pruned;
+    report
+}
+
+/// Prune the static transfer functions of a receiver, or its frames when it
+/// has some, the static ones being a copy of the first frame
+pub fn prune_receiver(receiver: &mut SignalReceiver, pruning: Pruning) -> PruningReport {
+    let mut report = PruningReport::default();
+    if receiver.frames.is_empty() {
+        for taps in &mut receiver.transfers {
+            report.add(&prune_transfer(taps, pruning));
+        }
+    } else {
+        for taps in receiver.frames.iter_mut().flatten() {
+            report.add(&prune_transfer(taps, pruning));
+        }
+        receiver.transfers = receiver.frames[0].clone();
+    }
+    report
+}
+
+/// Prune every receiver
+pub fn prune_receivers<'a>(
+    receivers: impl IntoIterator<Item = &'a mut SignalReceiver>,
+    pruning: Pruning,
+) -> PruningReport {
+    let mut report = PruningReport::default();
+    for receiver in receivers {
+        report.add(&prune_receiver(receiver, pruning));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::ReceptionKind;
+    use nalgebra::Point3;
+
+    fn taps(x: &[(usize, f32)]) -> Vec<SignalEvent> {
+        x.iter().map(|&(time, gain)| SignalEvent { time, gain }).collect()
+    }
+
+    fn pairs(x: &[SignalEvent]) -> Vec<(usize, f32)> {
+        x.iter().map(|x| (x.time, x.gain)).collect()
+    }
+
+    #[test]
+    fn threshold() {
+        let x = taps(&[(0, 1.0), (3, -0.5), (5, 0.01), (9, 0.2)]);
+        // 10 dB below 1.0 keeps 0.1 and above, whatever the sign
+        assert_eq!(pairs(&prune(&x, Pruning::Threshold(10.0))), [(0, 1.0), (3, -0.5), (9, 0.2)]);
+    }
+
+    #[test]
+    fn energy() {
+        let x = taps(&[(0, 0.1), (2, 0.6), (4, 0.3)]);
+        assert_eq!(pairs(&prune(&x, Pruning::Energy(0.8))), [(2, 0.6), (4, 0.3)]);
+        assert_eq!(pairs(&prune(&x, Pruning::Energy(1.0))), pairs(&x));
+        assert!(prune(&x, Pruning::Energy(0.0)).is_empty());
+    }
+
+    #[test]
+    fn cluster() {
+        let x = taps(&[(0, 0.1), (1, 0.4), (2, 0.2), (5, 0.3), (9, 0.1)]);
+        // The clusters start at 0, 5 and 9, each at the time of its strongest tap
+        let clustered = pairs(&prune(&x, Pruning::Cluster(3)));
+        assert_eq!(clustered.iter().map(|x| x.0).collect::<Vec<_>>(), [1, 5, 9]);
+        assert!((clustered[0].1 - 0.7).abs() < 1e-6);
+    }
+
+    #[test]
+    fn residual_of_disjoint_and_shared_taps() {
+        let before = taps(&[(0, 1.0), (2, 0.5), (4, -0.25)]);
+        let after = taps(&[(1, 0.125), (2, 0.25)]);
+        assert_eq!(residual(&before, &before), 0.0);
+        assert_eq!(residual(&before, &[]), 1.75);
+        assert_eq!(residual(&before, &after), 1.0 + 0.125 + 0.25 + 0.25);
+        assert_eq!(residual(&after, &before), residual(&before, &after));
+    }
+
+    #[test]
+    fn report() {
+        let mut x = taps(&[(0, 1.0), (1, -0.5), (2, 0.01)]);
+        let report = prune_transfer(&mut x, Pruning::Threshold(10.0));
+        assert_eq!((report.taps, report.kept), (3, 2));
+        assert!((report.energy - 1.51).abs() < 1e-6);
+        assert!((report.error - 0.01).abs() < 1e-6);
+        assert_eq!(report.relative_error(), report.max_error);
+        assert_eq!(x.len(), 2);
+    }
+
+    #[test]
+    fn frames_are_counted_once() {
+        let transfer = taps(&[(0, 1.0), (1, 0.001)]);
+        let mut receiver = SignalReceiver {
+            position: Point3::origin(),
+            transfers: vec![transfer.clone()],
+            paths: Vec::new(),
+            frames: vec![vec![transfer.clone()], vec![transfer]],
+            kind: ReceptionKind::None,
+        };
+        let report = prune_receiver(&mut receiver, Pruning::Threshold(10.0));
+        assert_eq!((report.taps, report.kept), (4, 2));
+        assert_eq!(pairs(&receiver.transfers[0]), [(0, 1.0)]);
+    }
+}
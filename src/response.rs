@@ -0,0 +1,206 @@
+// response.rs
+//! Frequency response and power-delay profile of a transfer function, written
+//! as CSV or as NumPy `.npy` arrays.
+
+use crate::antennas::SignalEvent;
+use crate::error::{Error, Result};
+use crate::TIME_PER_BEAT;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use rustfft::{num_complex::Complex, num_traits::Zero, FFTplanner};
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Frequencies a response is computed over, in Hz
+#[derive(Clone, Copy, Debug)]
+pub struct Band {
+    pub start: f32,
+    pub end: f32,
+    /// Largest spacing of the frequencies, the FFT may give a finer one
+    pub resolution: f32,
+}
+
+impl Default for Band {
+    /// Up to the Nyquist frequency, by 10 MHz
+    fn default() -> Self {
+        Self {
+            start: 0.0,
+            end: 0.5 / TIME_PER_BEAT,
+            resolution: 10e6,
+        }
+    }
+}
+
+pub struct FrequencyResponse {
+    /// In Hz
+    pub frequencies: Vec<f32>,
+    pub response: Vec<Complex<f32>>,
+}
+
+impl FrequencyResponse {
+    pub fn magnitude_db(&self) -> Vec<f32> {
+        self.response.iter().map(|x| 20.0 * x.norm().log10()).collect()
+    }
+
+    /// In radians, wrapped to ]-π, π]
+    pub fn phase(&self) -> Vec<f32> {
+        self.response.iter().map(|x| x.arg()).collect()
+    }
+
+    /// Columns `frequency,magnitude_db,phase`, in CSV or in NPY if `path` ends with `.npy`
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let rows = self
+            .frequencies
+            .iter()
+            .zip(self.magnitude_db())
+            .zip(self.phase())
+            .map(|((&f, m), p)| vec![f, m, p]);
+        write_table(path, &["frequency", "magnitude_db", "phase"], rows)
+    }
+}
+
+/// Frequency response of a transfer function sorted by time, by a zero-padded FFT
+pub fn frequency_response(taps: &[SignalEvent], band: Band) -> Result<FrequencyResponse> {
+    let sample_rate = 1.0 / TIME_PER_BEAT;
+    if !(band.resolution > 0.0 && 0.0 <= band.start && band.start <= band.end)
+        || band.end > sample_rate / 2.0
+    {
+        return Err(Error::InvalidBand {
+            start: band.start,
+            end: band.end,
+            resolution: band.resolution,
+        });
+    }
+
+    let length = taps.last().map_or(1, |x| x.time + 1);
+    let size = ((sample_rate / band.resolution).ceil() as usize)
+        .max(length)
+        .next_power_of_two();
+    let mut input = vec![Complex::zero(); size];
+    for tap in taps {
+        input[tap.time].re += tap.gain;
+    }
+    let mut output = vec![Complex::zero(); size];
+    FFTplanner::new(false)
+        .plan_fft(size)
+        .process(&mut input, &mut output);
+
+    let step = sample_rate / size as f32;
+    let first = (band.start / step).ceil() as usize;
+    let last = ((band.end / step).floor() as usize).min(size / 2);
+    let bins = first..=last;
+    Ok(FrequencyResponse {
+        frequencies: bins.clone().map(|k| k as f32 * step).collect(),
+        response: output[bins].to_vec(),
+    })
+}
+
+/// Delay in seconds and power of each tap, the gain of a tap being the
+/// energy of its rays as in `coverage`
+pub fn power_delay_profile(taps: &[SignalEvent]) -> Vec<(f32, f32)> {
+    taps.iter()
+        .map(|x| (x.time as f32 * TIME_PER_BEAT, x.gain.abs()))
+        .collect()
+}
+
+/// Columns `delay,power,power_db`, in CSV or in NPY if `path` ends with `.npy`
+pub fn write_power_delay_profile(taps: &[SignalEvent], path: &str) -> io::Result<()> {
+    let rows = power_delay_profile(taps)
+        .into_iter()
+        .map(|(delay, power)| vec![delay, power, 10.0 * power.log10()]);
+    write_table(path, &["delay", "power", "power_db"], rows)
+}
+
+fn write_table(
+    path: &str,
+    columns: &[&str],
+    rows: impl Iterator<Item = Vec<f32>>,
+) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    if path.ends_with(".npy") {
+        let rows: Vec<Vec<f32>> = rows.collect();
+        write_npy_header(&mut file, rows.len(), columns.len())?;
+        for x in rows.iter().flatten() {
+            file.write_f32::<LittleEndian>(*x)?;
+        }
+    } else {
+        writeln!(file, "{}", columns.join(","))?;
+        for row in rows {
+            let row: Vec<String> = row.iter().map(f32::to_string).collect();
+            writeln!(file, "{}", row.join(","))?;
+        }
+    }
+    file.flush()
+}
+
+/// Header of a version 1.0 `.npy` file of `rows` by `columns` little-endian f32
+fn write_npy_header(w: &mut impl Write, rows: usize, columns: usize) -> io::Result<()> {
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}",
+        rows, columns
+    );
+    // The data starts on a multiple of 64 bytes, the header ends with a newline
+    let unpadded = 10 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+    w.write_all(b"\x93NUMPY\x01\x00")?;
+    w.write_u16::<LittleEndian>(header.len() as u16)?;
+    w.write_all(header.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempFile;
+
+    #[test]
+    fn single_tap() {
+        // A delayed tap only turns the phase
+        let taps = [SignalEvent { time: 5, gain: 0.25 }];
+        let band = Band::default();
+        let response = frequency_response(&taps, band).unwrap();
+        assert!(response.frequencies.len() > 1);
+        assert!(response.frequencies.windows(2).all(|x| x[1] - x[0] <= band.resolution));
+        assert!(response.frequencies.iter().all(|&f| band.start <= f && f <= band.end));
+        for db in response.magnitude_db() {
+            assert!((db - 20.0 * 0.25f32.log10()).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn invalid_band() {
+        let nyquist = Band::default().end;
+        let bands = [
+            Band { end: nyquist * 1.1, ..Band::default() },
+            Band { resolution: 0.0, ..Band::default() },
+            Band { start: 2e9, end: 1e9, ..Band::default() },
+            Band { start: -1.0, ..Band::default() },
+        ];
+        for &band in &bands {
+            match frequency_response(&[], band) {
+                Err(Error::InvalidBand { .. }) => (),
+                _ => panic!("{:?} should be rejected", band),
+            }
+        }
+    }
+
+    #[test]
+    fn npy() {
+        for &(rows, columns) in &[(0, 3), (7, 3), (123_456, 2)] {
+            let mut header = Vec::new();
+            write_npy_header(&mut header, rows, columns).unwrap();
+            assert_eq!(header.len() % 64, 0);
+            assert!(header.starts_with(b"\x93NUMPY\x01\x00"));
+            assert_eq!(header.last(), Some(&b'\n'));
+            let length = u16::from_le_bytes([header[8], header[9]]) as usize;
+            assert_eq!(length + 10, header.len());
+        }
+
+        let taps = [SignalEvent { time: 1, gain: 1.0 }, SignalEvent { time: 3, gain: -0.5 }];
+        let file = TempFile::new("pdp.npy");
+        write_power_delay_profile(&taps, file.path()).unwrap();
+        let data = std::fs::read(file.path()).unwrap();
+        assert_eq!(data.len(), 128 + 2 * 3 * 4);
+    }
+}
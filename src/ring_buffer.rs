@@ -23,7 +23,7 @@ where
     pub fn pop(&mut self) -> T {
         let elem = {
             let in_buf = unsafe { self.buffer.get_unchecked_mut(self.reader) };
-            std::mem::replace(in_buf, T::default())
+            std::mem::take(in_buf)
         };
 
         // Optimized modular increment
@@ -36,21 +36,6 @@ where
         elem
     }
 
-    pub fn get(&self, index: usize) -> Option<&T> {
-        if index < self.buffer.len() {
-            let index = self.reader + index;
-            if index >= self.buffer.len() {
-                // If the target is too far up, loop back.
-                let index = index - self.buffer.len();
-                self.buffer.get(index)
-            } else {
-                self.buffer.get(index)
-            }
-        } else {
-            None
-        }
-    }
-
     pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
         if index < self.buffer.len() {
             let index = self.reader + index;
@@ -65,8 +50,4 @@ where
             None
         }
     }
-
-    pub fn len(&self) -> usize {
-        self.buffer.len()
-    }
 }
@@ -0,0 +1,511 @@
+// scene.rs
+//! Scene files: obstacles, materials and antennas described in RON.
+
+use crate::antennas::{
+    create_bvt_tuple, create_bvt_tuple_blockage, SceneObject, SignalEmitter, SignalReceiver,
+    WorldDescriptor,
+};
+use crate::blockage::{Blockage, FoliageModel, BODY_LOSS};
+use crate::city::CityParameters;
+use crate::floorplan::FloorPlan;
+use crate::materials::{self, Material, MaterialLibrary};
+use crate::mesh;
+use crate::simulation::{EmissionKind, ReceptionKind};
+use crate::terrain::{self, HeightMap};
+use crate::waves::TracerConfig;
+
+use nalgebra::{Isometry3, Point3, Translation3, Unit, UnitQuaternion, Vector3};
+use ncollide3d::bounding_volume::aabb::AABB;
+use ncollide3d::shape::{Ball, Capsule, Cone, ConvexHull, Cuboid, Cylinder, Plane};
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SceneDescription {
+    #[serde(default)]
+    pub tracer: TracerConfig,
+    /// Instants at which the scene is traced when objects move
+    #[serde(default)]
+    pub timeline: Option<Timeline>,
+    /// Added to the built-in library, replacing entries of the same name
+    #[serde(default)]
+    pub materials: MaterialLibrary,
+    /// Groups of objects defined once and placed with `Instance` shapes
+    #[serde(default)]
+    pub groups: HashMap<String, Vec<ObjectDescription>>,
+    #[serde(default)]
+    pub objects: Vec<ObjectDescription>,
+    #[serde(default)]
+    pub antennas: Vec<AntennaDescription>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Transform {
+    #[serde(default)]
+    pub translation: [f32; 3],
+    /// Euler angles in degrees: roll, pitch and yaw around x, y and z
+    #[serde(default)]
+    pub rotation: [f32; 3],
+}
+
+/// `frames` instants `step` seconds apart, from `start`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Timeline {
+    #[serde(default)]
+    pub start: f32,
+    pub step: f32,
+    pub frames: usize,
+}
+
+/// Transform of a moving object at an instant, in seconds of simulated time
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Keyframe {
+    pub time: f32,
+    pub transform: Transform,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum MaterialDescription {
+    /// Entry of the library, see `materials::library`
+    Named(String),
+    /// Lossless material of the given refractive index
+    Dielectric(f32),
+    Custom(Material),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ShapeDescription {
+    Plane { normal: [f32; 3] },
+    Cuboid { half_extents: [f32; 3] },
+    Ball { radius: f32 },
+    /// Cylinders, cones and capsules are along the y axis before rotation
+    Cylinder { half_height: f32, radius: f32 },
+    Cone { half_height: f32, radius: f32 },
+    Capsule { half_height: f32, radius: f32 },
+    /// Convex hull of the points, in the object's frame
+    ConvexHull { points: Vec<[f32; 3]> },
+    Foliage { half_extents: [f32; 3], model: FoliageModel },
+    /// Standing human, the translation is the center of the feet
+    Body { radius: f32, height: f32 },
+    /// Materials of the OBJ file by `usemtl` name, `material` is the fallback
+    Obj {
+        path: String,
+        #[serde(default)]
+        materials: HashMap<String, MaterialDescription>,
+    },
+    Stl { path: String },
+    /// Building extruded from a floor plan, with its own materials
+    FloorPlan(FloorPlan),
+    /// Procedural city with its ground, centered on the translation
+    City(CityParameters),
+    /// ESRI ASCII grid (.asc) or CSV heightmap, the transform places its north-west corner
+    Terrain {
+        path: String,
+        #[serde(default)]
+        cellsize: Option<f32>,
+    },
+    /// Objects of a group placed relative to the transform, the `material`
+    /// is used for those without one. Groups may contain instances.
+    Instance(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ObjectDescription {
+    pub shape: ShapeDescription,
+    #[serde(default)]
+    pub transform: Transform,
+    #[serde(default)]
+    pub material: Option<MaterialDescription>,
+    /// Keyframes replacing `transform`, interpolated linearly. The object
+    /// stays still before the first one and after the last one.
+    #[serde(default)]
+    pub trajectory: Vec<Keyframe>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum AntennaRole {
+    Emitter { max_power: f32, kind: EmissionKind },
+    Receiver(ReceptionKind),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AntennaDescription {
+    pub name: String,
+    pub position: [f32; 3],
+    pub role: AntennaRole,
+}
+
+/// Error in a scene file, `entry` locates the faulty part (e.g. `objects[3]`)
+#[derive(Debug)]
+pub struct SceneError {
+    pub entry: String,
+    pub message: String,
+}
+
+impl SceneError {
+    pub fn new(entry: impl ToString, message: impl ToString) -> Self {
+        Self {
+            entry: entry.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.entry, self.message)
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+/// What objects are built in: resources of the scene and enclosing instances
+struct Context<'a> {
+    dir: &'a Path,
+    library: &'a MaterialLibrary,
+    groups: &'a HashMap<String, Vec<ObjectDescription>>,
+    /// Transform of the enclosing instances
+    transform: Isometry3<f32>,
+    /// Material of the innermost enclosing instance that has one
+    material: Option<&'a MaterialDescription>,
+    /// Enclosing groups, a group cannot contain itself
+    instances: Vec<&'a str>,
+    /// Instant at which moving objects are placed
+    time: f32,
+}
+
+impl MaterialDescription {
+    pub fn resolve(&self, entry: &str, library: &MaterialLibrary) -> Result<Material, SceneError> {
+        let material = match *self {
+            MaterialDescription::Named(ref name) => library
+                .get(name)
+                .cloned()
+                .ok_or_else(|| SceneError::new(entry, format!("unknown material {}", name)))?,
+            MaterialDescription::Dielectric(n) => Material::dielectric(n),
+            MaterialDescription::Custom(ref material) => material.clone(),
+        };
+        match material.check() {
+            Some(message) => Err(SceneError::new(entry, message)),
+            None => Ok(material),
+        }
+    }
+}
+
+impl Timeline {
+    pub fn times(&self) -> Vec<f32> {
+        (0..self.frames)
+            .map(|i| self.start + i as f32 * self.step)
+            .collect()
+    }
+}
+
+impl Transform {
+    pub fn isometry(&self) -> Isometry3<f32> {
+        let t = self.translation;
+        let r = self.rotation;
+        Isometry3::from_parts(
+            Translation3::new(t[0], t[1], t[2]),
+            UnitQuaternion::from_euler_angles(
+                r[0].to_radians(),
+                r[1].to_radians(),
+                r[2].to_radians(),
+            ),
+        )
+    }
+}
+
+fn positive(entry: &str, name: &str, values: &[f32]) -> Result<(), SceneError> {
+    if values.iter().all(|&x| x > 0.0 && x.is_finite()) {
+        Ok(())
+    } else {
+        Err(SceneError::new(entry, format!("{} must be positive", name)))
+    }
+}
+
+impl SceneDescription {
+    pub fn load(path: &str) -> Result<(Self, PathBuf), SceneError> {
+        let file = std::fs::File::open(path).map_err(|e| SceneError::new(path, e))?;
+        let scene = ron::de::from_reader(std::io::BufReader::new(file))
+            .map_err(|e| SceneError::new(path, e))?;
+        let dir = Path::new(path)
+            .parent()
+            .map(|x| x.to_owned())
+            .unwrap_or_default();
+        Ok((scene, dir))
+    }
+
+    /// Validate the scene and build it, at the start of its timeline if
+    /// objects move. Relative paths are resolved from `dir`.
+    pub fn build(&self, dir: &Path) -> Result<WorldDescriptor, SceneError> {
+        self.build_at(dir, self.timeline.as_ref().map_or(0.0, |t| t.start))
+    }
+
+    /// Instants of the timeline, empty for a static scene
+    pub fn times(&self) -> Vec<f32> {
+        self.timeline.as_ref().map(Timeline::times).unwrap_or_default()
+    }
+
+    /// Build the scene with the moving objects placed at `time`
+    pub fn build_at(&self, dir: &Path, time: f32) -> Result<WorldDescriptor, SceneError> {
+        if let Some(ref timeline) = self.timeline {
+            positive("timeline", "step", &[timeline.step])?;
+            if timeline.start < 0.0 {
+                return Err(SceneError::new("timeline", "start must not be negative"));
+            }
+            if timeline.frames == 0 {
+                return Err(SceneError::new("timeline", "frames must be positive"));
+            }
+        }
+
+        let mut library = materials::library();
+        for (name, material) in &self.materials {
+            if let Some(message) = material.check() {
+                return Err(SceneError::new(format!("materials.{}", name), message));
+            }
+            library.insert(name.clone(), material.clone());
+        }
+
+        let context = Context {
+            dir,
+            library: &library,
+            groups: &self.groups,
+            transform: Isometry3::identity(),
+            material: None,
+            instances: Vec::new(),
+            time,
+        };
+        let mut collisions = Vec::with_capacity(self.objects.len());
+        for (i, object) in self.objects.iter().enumerate() {
+            let entry = format!("objects[{}]", i);
+            collisions.extend(object.build(&entry, &context)?);
+        }
+
+        let count = self.antennas.len();
+        let mut world = WorldDescriptor {
+            emitters: Vec::with_capacity(count),
+            receivers: Vec::with_capacity(count),
+            names: Vec::with_capacity(count),
+            collisions,
+        };
+
+        let mut names = HashSet::new();
+        for (i, antenna) in self.antennas.iter().enumerate() {
+            let entry = format!("antennas[{}] ({})", i, antenna.name);
+            if !names.insert(&antenna.name) {
+                return Err(SceneError::new(entry, "duplicate antenna name"));
+            }
+
+            let p = antenna.position;
+            let position = Point3::new(p[0], p[1], p[2]);
+            match antenna.role {
+                AntennaRole::Emitter {
+                    max_power,
+                    ref kind,
+                } => {
+                    positive(&entry, "max_power", &[max_power])?;
+                    world.emitters.push(Some(SignalEmitter {
+                        position,
+                        max_power,
+                        kind: kind.clone(),
+                    }));
+                    world.receivers.push(None);
+                }
+                AntennaRole::Receiver(ref kind) => {
+                    world.emitters.push(None);
+                    world.receivers.push(Some(SignalReceiver {
+                        position,
+                        transfers: vec![Vec::new(); count],
+                        paths: Vec::new(),
+                        frames: Vec::new(),
+                        kind: kind.clone(),
+                    }));
+                }
+            }
+            world.names.push(antenna.name.clone());
+        }
+
+        Ok(world)
+    }
+}
+
+impl ObjectDescription {
+    fn material(&self, entry: &str, context: &Context) -> Result<Material, SceneError> {
+        self.material
+            .as_ref()
+            .or(context.material)
+            .ok_or_else(|| SceneError::new(entry, "missing material"))?
+            .resolve(entry, context.library)
+    }
+
+    /// Transform at `time`, following the trajectory if there is one
+    fn transform_at(&self, entry: &str, time: f32) -> Result<Isometry3<f32>, SceneError> {
+        let keys = &self.trajectory;
+        if keys.windows(2).any(|w| w[0].time >= w[1].time) {
+            return Err(SceneError::new(entry, "keyframes must be sorted by time"));
+        }
+
+        Ok(match keys.iter().position(|k| k.time > time) {
+            _ if keys.is_empty() => self.transform.isometry(),
+            Some(0) => keys[0].transform.isometry(),
+            None => keys[keys.len() - 1].transform.isometry(),
+            Some(i) => {
+                let t = (time - keys[i - 1].time) / (keys[i].time - keys[i - 1].time);
+                let (a, b) = (keys[i - 1].transform.isometry(), keys[i].transform.isometry());
+                Isometry3::from_parts(
+                    Translation3::from(a.translation.vector * (1.0 - t) + b.translation.vector * t),
+                    a.rotation.slerp(&b.rotation, t),
+                )
+            }
+        })
+    }
+
+    fn build<'a>(
+        &'a self,
+        entry: &str,
+        context: &Context<'a>,
+    ) -> Result<Vec<(SceneObject, AABB<f32>)>, SceneError> {
+        let transform = context.transform * self.transform_at(entry, context.time)?;
+        let library = context.library;
+        let path = |path: &str| context.dir.join(path).to_string_lossy().into_owned();
+
+        Ok(match self.shape {
+            ShapeDescription::Plane { normal } => {
+                let material = self.material(entry, context)?;
+                let normal = Vector3::from(normal);
+                if normal.norm() == 0.0 {
+                    return Err(SceneError::new(entry, "plane normal is zero"));
+                }
+                vec![create_bvt_tuple(
+                    &Plane::new(Unit::new_normalize(normal)),
+                    transform,
+                    material,
+                )]
+            }
+            ShapeDescription::Cuboid { half_extents } => {
+                let material = self.material(entry, context)?;
+                positive(entry, "half_extents", &half_extents)?;
+                vec![create_bvt_tuple(&Cuboid::new(half_extents.into()), transform, material)]
+            }
+            ShapeDescription::Ball { radius } => {
+                let material = self.material(entry, context)?;
+                positive(entry, "radius", &[radius])?;
+                vec![create_bvt_tuple(&Ball::new(radius), transform, material)]
+            }
+            ShapeDescription::Cylinder {
+                half_height,
+                radius,
+            } => {
+                let material = self.material(entry, context)?;
+                positive(entry, "half_height and radius", &[half_height, radius])?;
+                vec![create_bvt_tuple(&Cylinder::new(half_height, radius), transform, material)]
+            }
+            ShapeDescription::Cone {
+                half_height,
+                radius,
+            } => {
+                let material = self.material(entry, context)?;
+                positive(entry, "half_height and radius", &[half_height, radius])?;
+                vec![create_bvt_tuple(&Cone::new(half_height, radius), transform, material)]
+            }
+            ShapeDescription::Capsule {
+                half_height,
+                radius,
+            } => {
+                let material = self.material(entry, context)?;
+                positive(entry, "half_height and radius", &[half_height, radius])?;
+                vec![create_bvt_tuple(&Capsule::new(half_height, radius), transform, material)]
+            }
+            ShapeDescription::ConvexHull { ref points } => {
+                let material = self.material(entry, context)?;
+                let points: Vec<Point3<f32>> = points
+                    .iter()
+                    .map(|p| Point3::new(p[0], p[1], p[2]))
+                    .collect();
+                let hull = ConvexHull::try_from_points(&points).ok_or_else(|| {
+                    SceneError::new(entry, "convex hull needs 4 points not in a plane")
+                })?;
+                vec![create_bvt_tuple(&hull, transform, material)]
+            }
+            ShapeDescription::FloorPlan(ref plan) => plan.extrude(entry, &transform, library)?,
+            ShapeDescription::City(ref city) => city.generate(entry, &transform, library)?,
+            ShapeDescription::Foliage {
+                half_extents,
+                model,
+            } => {
+                positive(entry, "half_extents", &half_extents)?;
+                vec![create_bvt_tuple_blockage(
+                    &Cuboid::new(half_extents.into()),
+                    transform,
+                    Blockage::Foliage(model),
+                )]
+            }
+            ShapeDescription::Body { radius, height } => {
+                positive(entry, "radius and height", &[radius, height])?;
+                let center = transform * Translation3::new(0.0, height / 2.0, 0.0);
+                vec![create_bvt_tuple_blockage(
+                    &Cylinder::new(height / 2.0, radius),
+                    center,
+                    Blockage::Body(BODY_LOSS),
+                )]
+            }
+            ShapeDescription::Obj {
+                path: ref file,
+                ref materials,
+            } => {
+                let default = self.material(entry, context)?;
+                let materials = materials
+                    .iter()
+                    .map(|(name, m)| Ok((name.clone(), m.resolve(entry, library)?)))
+                    .collect::<Result<_, SceneError>>()?;
+                mesh::load_obj(&path(file), transform, &materials, default)
+                    .map_err(|e| SceneError::new(entry, e))?
+            }
+            ShapeDescription::Stl { path: ref file } => {
+                let material = self.material(entry, context)?;
+                vec![mesh::load_stl(&path(file), transform, material)
+                    .map_err(|e| SceneError::new(entry, e))?]
+            }
+            ShapeDescription::Terrain {
+                path: ref file,
+                cellsize,
+            } => {
+                let material = self.material(entry, context)?;
+                let map = if file.ends_with(".csv") {
+                    let cellsize = cellsize
+                        .ok_or_else(|| SceneError::new(entry, "CSV heightmaps need a cellsize"))?;
+                    HeightMap::from_csv(&path(file), cellsize)
+                } else {
+                    HeightMap::from_esri_ascii(&path(file))
+                }
+                .map_err(|e| SceneError::new(entry, e))?;
+                vec![terrain::terrain(&map, transform, material)]
+            }
+            ShapeDescription::Instance(ref name) => {
+                let group = context
+                    .groups
+                    .get(name)
+                    .ok_or_else(|| SceneError::new(entry, format!("unknown group {}", name)))?;
+                if context.instances.contains(&name.as_str()) {
+                    return Err(SceneError::new(entry, format!("group {} contains itself", name)));
+                }
+                let mut instances = context.instances.clone();
+                instances.push(name);
+                let inner = Context {
+                    transform,
+                    material: self.material.as_ref().or(context.material),
+                    instances,
+                    ..*context
+                };
+
+                let mut objects = Vec::new();
+                for (i, object) in group.iter().enumerate() {
+                    let entry = format!("{} > groups.{}[{}]", entry, name, i);
+                    objects.extend(object.build(&entry, &inner)?);
+                }
+                objects
+            }
+        })
+    }
+}
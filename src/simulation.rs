@@ -1,27 +1,33 @@
 use serde::{Deserialize, Serialize};
 
 use crate::antennas::{SignalEvent, WorldDescriptor};
+use crate::channels::{self, SyntheticChannel};
+use crate::error::{Error, Result};
+use crate::pruning::{self, Pruning, PruningReport};
+use crate::scene::{AntennaDescription, SceneDescription};
+use crate::solution::Metadata;
+use crate::statistics::{self, LinkStatistics};
+use crate::validation::{Issue, Severity};
+use crate::waves::{TracerConfig, TracerReport};
+use crate::TIME_PER_BEAT;
 use crate::systems::{
     moving::{MovementHandler, ProxyReception},
     ofdm::{OFDMEmitter, OFDMReceiver},
     propagation::{Emission, PropagationSystem, Reception},
     simple_wave::{SimpleWave, SimpleWaveEmitter},
-    tracker::TrackerSystem,
+    tracker::{self, TrackerSystem},
 };
 
-use specs::{Component, DispatcherBuilder, Entity, VecStorage, World, WriteStorage};
+use bit_vec::BitVec;
+use byteorder::{LittleEndian, ReadBytesExt};
+use specs::{DispatcherBuilder, Entity, World, WriteStorage};
 
-use ron::ser::PrettyConfig;
+use std::path::PathBuf;
 
-fn ron_pretty() -> PrettyConfig {
-    PrettyConfig {
-        new_line: "\n".to_owned(),
-        indentor: "\t".to_owned(),
-        ..Default::default()
-    }
-}
+/// Length of a run when not given, in beats
+pub const DEFAULT_BEATS: usize = 0x20000;
 
-fn check_valid(transf: &Vec<SignalEvent>) -> bool {
+fn check_valid(transf: &[SignalEvent]) -> bool {
     for i in 1..transf.len() {
         if transf[i - 1].time >= transf[i].time {
             return false;
@@ -33,6 +39,15 @@ fn check_valid(transf: &Vec<SignalEvent>) -> bool {
 pub struct Simulation {
     pub world: World,
     pub descriptor: WorldDescriptor,
+    pub config: TracerConfig,
+    /// Hash of the scene the transfer functions were computed for
+    pub scene_hash: Option<u64>,
+    /// Instants of the frames in seconds, empty for a static scene
+    pub timeline: Vec<f32>,
+    /// How the transfer functions were computed
+    pub metadata: Option<Metadata>,
+    /// Warnings of the last solve or load, left for the caller to report
+    pub warnings: Vec<Issue>,
     moving_points: Vec<Entity>,
 }
 
@@ -61,32 +76,46 @@ impl Simulation {
         Self {
             world: World::new(),
             descriptor,
+            config: TracerConfig::default(),
+            scene_hash: None,
+            timeline: Vec::new(),
+            metadata: None,
+            warnings: Vec::new(),
             moving_points: Vec::new(),
         }
     }
 
+    /// Save in RON if `path` ends with `.ron`, in binary otherwise, see `solution`
     pub fn save_solution(&mut self, path: &str) {
+        self.try_save_solution(path)
+            .unwrap_or_else(|e| panic!("Failed to save solution: {}", e));
+    }
+
+    pub fn try_save_solution(&self, path: &str) -> Result<()> {
         let descriptor = &self.descriptor;
 
         let serializable = crate::antennas::SerializableWorld {
             emitters: descriptor.emitters.clone(),
             receivers: descriptor.receivers.clone(),
             names: descriptor.names.clone(),
+            scene_hash: self.scene_hash,
+            timeline: self.timeline.clone(),
+            metadata: self.metadata.clone(),
         };
 
-        let data = ron::ser::to_string_pretty(&serializable, ron_pretty())
-            .expect("Failed to serialize the simulation");
-        std::fs::write(path, data).expect("Failed to save solution to disk");
+        crate::solution::save(&serializable, path).map_err(|e| Error::io(path, e))
     }
 
+    /// Load a solution in RON or in binary
     pub fn from_solution(path: &str) -> Self {
-        let serializable: crate::antennas::SerializableWorld =
-            ron::de::from_reader(std::io::BufReader::new(
-                std::fs::File::open(path).expect("Could not open simulation file"),
-            ))
-            .expect("Could not deserialize simulation");
+        Self::try_from_solution(path)
+            .unwrap_or_else(|e| panic!("Could not load simulation file: {}", e))
+    }
 
-        Self {
+    pub fn try_from_solution(path: &str) -> Result<Self> {
+        let serializable = crate::solution::load(path).map_err(|e| Error::io(path, e))?;
+
+        Ok(Self {
             world: World::new(),
             descriptor: WorldDescriptor {
                 emitters: serializable.emitters,
@@ -94,23 +123,215 @@ impl Simulation {
                 names: serializable.names,
                 collisions: Vec::new(),
             },
+            config: TracerConfig::default(),
+            scene_hash: serializable.scene_hash,
+            timeline: serializable.timeline,
+            metadata: serializable.metadata,
+            warnings: Vec::new(),
             moving_points: Vec::new(),
+        })
+    }
+
+    /// Load a solution computed for the scene of hash `expected`, see
+    /// `fingerprint`. Panics if the solution comes from another scene.
+    pub fn load_solution(path: &str, expected: u64) -> Self {
+        Self::try_load_solution(path, expected).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_load_solution(path: &str, expected: u64) -> Result<Self> {
+        let mut sim = Self::try_from_solution(path)?;
+        match sim.scene_hash {
+            Some(found) if found != expected => {
+                return Err(Error::SceneMismatch {
+                    path: path.to_owned(),
+                    found,
+                    expected,
+                })
+            }
+            Some(_) => (),
+            None => sim.warnings.push(Issue {
+                severity: Severity::Warning,
+                objects: Vec::new(),
+                antenna: None,
+                message: format!("{} has no scene hash, it cannot be checked", path),
+            }),
         }
+        Ok(sim)
+    }
+
+    /// Index of the antenna called `name`
+    pub fn antenna(&self, name: &str) -> Option<usize> {
+        self.descriptor.names.iter().position(|x| x == name)
+    }
+
+    /// Transfer function from `emitter` to `receiver`, sorted by time
+    pub fn transfer(&self, receiver: &str, emitter: &str) -> Option<&[SignalEvent]> {
+        let receiver = self.descriptor.receivers[self.antenna(receiver)?].as_ref()?;
+        receiver
+            .transfers
+            .get(self.antenna(emitter)?)
+            .map(|x| &x[..])
+    }
+
+    /// Data decoded so far by the OFDM receiver `receiver`
+    pub fn ofdm_data(&self, receiver: &str) -> Option<BitVec> {
+        use specs::Join;
+        let recs = self.world.read_storage::<Reception>();
+        let ofdm = self.world.read_storage::<OFDMReceiver>();
+        (&recs, &ofdm)
+            .join()
+            .find(|(x, _)| x.label == receiver)
+            .map(|(_, x)| x.data_buffer.clone())
+    }
+
+    /// Signal of a tracked antenna over the last run, one value per beat
+    pub fn tracked(&self, name: &str) -> Result<Vec<f32>> {
+        let path = tracker::output_path(name);
+        let data = std::fs::read(&path).map_err(|e| Error::io(&path, e))?;
+        let mut signal = vec![0.0; data.len() / 4];
+        (&data[..])
+            .read_f32_into::<LittleEndian>(&mut signal)
+            .map_err(|e| Error::io(&path, e))?;
+        Ok(signal)
+    }
+
+    /// Remove the weakest taps of the transfer functions, see `pruning`
+    pub fn prune(&mut self, pruning: Pruning) -> PruningReport {
+        let receivers = self.descriptor.receivers.iter_mut().flatten();
+        pruning::prune_receivers(receivers, pruning)
+    }
+
+    /// Channel metrics of each link, see `statistics`. The max excess delay
+    /// counts the taps at most `threshold` dB below the strongest.
+    pub fn statistics(&self, threshold: f32) -> Vec<LinkStatistics> {
+        // A loaded solution has the configuration it was traced with
+        let config = self.metadata.as_ref().map_or(&self.config, |x| &x.tracer);
+        statistics::statistics(&self.descriptor, config.receiver_radius, threshold)
+    }
+
+    pub fn validate(&self) -> Vec<Issue> {
+        crate::validation::validate(&self.descriptor, &self.config)
     }
 
+    /// Validate the scene and trace it. Panics if the scene has errors.
     pub fn solve(&mut self) {
-        crate::waves::tracing(&mut self.descriptor);
+        self.try_solve().unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    pub fn try_solve(&mut self) -> Result<()> {
+        self.scene_hash = Some(crate::fingerprint::scene_hash(&self.descriptor, &self.config));
+        let start = chrono::Utc::now();
+        let (report, warnings) = trace(&mut self.descriptor, &self.config)?;
+        self.warnings = warnings;
+        self.metadata = Some(Metadata::new(&self.config, chrono::Utc::now() - start, vec![report]));
+        Ok(())
+    }
+
+    /// Trace a scene with moving objects at each of `times`, `build` giving
+    /// the scene at an instant. The antennas must be those of the descriptor.
+    /// The first frame also gives the transfer functions of the descriptor.
+    pub fn solve_timeline(&mut self, times: &[f32], build: impl Fn(f32) -> WorldDescriptor) {
+        self.try_solve_timeline(times, |time| Ok(build(time)))
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    pub fn try_solve_timeline(
+        &mut self,
+        times: &[f32],
+        build: impl Fn(f32) -> Result<WorldDescriptor>,
+    ) -> Result<()> {
+        let start = chrono::Utc::now();
+        let mut hashes = Vec::with_capacity(times.len());
+        let mut reports = Vec::with_capacity(times.len());
+        self.warnings.clear();
+        for (i, &time) in times.iter().enumerate() {
+            let mut frame = build(time)?;
+            if frame.names != self.descriptor.names {
+                return Err(Error::FrameAntennas { frame: i });
+            }
+            hashes.push((time, crate::fingerprint::scene_hash(&frame, &self.config)));
+            let (report, warnings) = trace(&mut frame, &self.config)?;
+            reports.push(report);
+            // Objects that do not move give the same warnings at every frame
+            for issue in warnings {
+                if !self.warnings.iter().any(|x| x.message == issue.message) {
+                    self.warnings.push(issue);
+                }
+            }
+
+            let receivers = self.descriptor.receivers.iter_mut().zip(frame.receivers);
+            for (receiver, traced) in receivers {
+                if let (Some(receiver), Some(traced)) = (receiver.as_mut(), traced) {
+                    if i == 0 {
+                        receiver.transfers = traced.transfers.clone();
+                        receiver.paths = traced.paths;
+                    }
+                    receiver.frames.push(traced.transfers);
+                }
+            }
+        }
+
+        self.timeline = times.to_vec();
+        self.scene_hash = Some(crate::fingerprint::timeline_hash(&hashes));
+        self.metadata = Some(Metadata::new(&self.config, chrono::Utc::now() - start, reports));
+        Ok(())
     }
 
     pub fn instanciate(&mut self) {
+        self.try_instanciate().unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /// Beat at which each frame of the timeline starts
+    fn timeline_beats(&self) -> Vec<usize> {
+        self.timeline
+            .iter()
+            .map(|&t| (t / TIME_PER_BEAT).round() as usize)
+            .collect()
+    }
+
+    /// Check the timeline and the transfer functions then create the entities
+    /// of the antennas
+    pub fn try_instanciate(&mut self) -> Result<()> {
+        // Frames starting on the same beat cannot be interpolated
+        let beats = self.timeline_beats();
+        if let Some(k) = (1..beats.len()).find(|&k| beats[k] <= beats[k - 1]) {
+            return Err(Error::Timeline {
+                index: k,
+                previous: self.timeline[k - 1],
+                time: self.timeline[k],
+            });
+        }
+
         let world = &self.descriptor;
 
+        for (i, rec) in world.receivers.iter().enumerate() {
+            let rec = match *rec {
+                Some(ref rec) => rec,
+                None => continue,
+            };
+            if !self.timeline.is_empty() && rec.frames.len() != self.timeline.len() {
+                return Err(Error::FrameCount {
+                    receiver: world.names[i].clone(),
+                    found: rec.frames.len(),
+                    expected: self.timeline.len(),
+                });
+            }
+            let sets = std::iter::once(&rec.transfers).chain(&rec.frames);
+            for transfers in sets {
+                if let Some(k) = transfers.iter().position(|x| !check_valid(x)) {
+                    return Err(Error::UnsortedTransfer {
+                        receiver: world.names[i].clone(),
+                        emitter: world.names[k].clone(),
+                    });
+                }
+            }
+        }
+
         self.world.register::<Reception>();
         self.world.register::<ProxyReception>();
         self.world.register::<Emission>();
         self.world.register::<OFDMReceiver>();
         self.world.register::<OFDMEmitter>();
-        self.world.register::<Name>();
         self.world.register::<SimpleWaveEmitter>();
 
         let mut entities: Vec<(AntennaKind, Entity)> = Vec::with_capacity(world.names.len());
@@ -151,24 +372,28 @@ impl Simulation {
         self.world.exec(|mut recs: WriteStorage<Reception>| {
             for i in 0..world.names.len() {
                 if let Some(ref rec) = world.receivers[i] {
+                    // A static scene is a single frame
+                    let frames = if rec.frames.is_empty() {
+                        std::slice::from_ref(&rec.transfers)
+                    } else {
+                        &rec.frames[..]
+                    };
+
                     let mut transfer = Vec::with_capacity(rec.transfers.len());
                     for k in 0..rec.transfers.len() {
                         if entities[k].0 == AntennaKind::Emit {
-                            if !check_valid(&rec.transfers[k]) {
-                                panic!("Transfer function for rec {} emit {} not sorted", i, k);
-                            }
-
-                            // (entity, transf, max_time)
-                            transfer.push((
-                                entities[k].1,
-                                rec.transfers[k].clone(),
-                                rec.transfers[k]
-                                    .iter()
-                                    .map(|x| x.time)
-                                    .max()
-                                    .map(|x| x + 1)
-                                    .unwrap_or(0),
-                            ));
+                            let transfers: Vec<Vec<SignalEvent>> =
+                                frames.iter().map(|x| x[k].clone()).collect();
+
+                            // (entity, transf by frame, max_time)
+                            let max_time = transfers
+                                .iter()
+                                .flatten()
+                                .map(|x| x.time)
+                                .max()
+                                .map(|x| x + 1)
+                                .unwrap_or(0);
+                            transfer.push((entities[k].1, transfers, max_time));
                         }
                     }
                     recs.insert(
@@ -179,15 +404,31 @@ impl Simulation {
                 }
             }
         });
+        Ok(())
     }
 
     pub fn start(&mut self, to_track: Vec<String>, time: usize) {
+        self.try_start(to_track, time)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /// Run for `time` beats, writing the signals of the `to_track` antennas in `output`
+    pub fn try_start(&mut self, to_track: Vec<String>, time: usize) -> Result<()> {
+        if let Some(name) = to_track.iter().find(|x| !self.descriptor.names.contains(*x)) {
+            return Err(Error::UnknownAntenna(name.clone()));
+        }
+        let tracker = TrackerSystem::try_new(to_track)?;
+
         let mut dispatcher = DispatcherBuilder::new()
             .with(SimpleWave, "simple_wave", &[])
             .with(crate::systems::ofdm::OFDMEmit, "odfm_emit", &[])
             .with(crate::systems::ofdm::OFDMReceive, "ofdm_rec", &[])
-            .with(PropagationSystem, "propagation_system", &[])
-            .with(TrackerSystem::new(to_track), "tracker_system", &[])
+            .with(
+                PropagationSystem::new(self.timeline_beats()),
+                "propagation_system",
+                &[],
+            )
+            .with(tracker, "tracker_system", &[])
             .with(
                 MovementHandler::new(self.moving_points.clone()),
                 "movement_handler",
@@ -195,18 +436,151 @@ impl Simulation {
             )
             .build();
 
-        println!("Dispatching!");
-
         for _ in 0..time {
-            dispatcher.dispatch(&mut self.world.res);
+            dispatcher.dispatch(&self.world.res);
         }
+        Ok(())
     }
 }
 
-struct Name {
-    name: String,
+/// Sets up a `Simulation` from a scene: traced or loaded, instanciated, then run
+pub struct SimulationBuilder {
+    scene: SceneDescription,
+    dir: PathBuf,
+    beats: usize,
+    tracked: Option<Vec<String>>,
+    solution: Option<String>,
+    channel: Option<SyntheticChannel>,
+    pruning: Option<Pruning>,
 }
 
-impl Component for Name {
-    type Storage = VecStorage<Name>;
+impl SimulationBuilder {
+    pub fn new(scene: SceneDescription) -> Self {
+        Self {
+            scene,
+            dir: PathBuf::new(),
+            beats: DEFAULT_BEATS,
+            tracked: None,
+            solution: None,
+            channel: None,
+            pruning: None,
+        }
+    }
+
+    /// Scene file, its relative paths are resolved from its directory
+    pub fn from_file(path: &str) -> Result<Self> {
+        let (scene, dir) = SceneDescription::load(path)?;
+        Ok(Self::new(scene).dir(dir))
+    }
+
+    /// Directory the relative paths of the scene are resolved from
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = dir.into();
+        self
+    }
+
+    pub fn antenna(mut self, antenna: AntennaDescription) -> Self {
+        self.scene.antennas.push(antenna);
+        self
+    }
+
+    /// Replaces the tracer configuration of the scene
+    pub fn tracer(mut self, config: TracerConfig) -> Self {
+        self.scene.tracer = config;
+        self
+    }
+
+    /// Length of the run, in beats of `TIME_PER_BEAT`
+    pub fn beats(mut self, beats: usize) -> Self {
+        self.beats = beats;
+        self
+    }
+
+    /// Antennas whose signals are written by the run, all of them by default
+    pub fn track(mut self, names: Vec<String>) -> Self {
+        self.tracked = Some(names);
+        self
+    }
+
+    /// Load the transfer functions from a solution of this scene instead of tracing it
+    pub fn solution(mut self, path: impl ToString) -> Self {
+        self.solution = Some(path.to_string());
+        self
+    }
+
+    /// Use a synthetic channel between every pair of antennas instead of tracing
+    /// the scene, incompatible with `solution`
+    pub fn channel(mut self, channel: SyntheticChannel) -> Self {
+        self.channel = Some(channel);
+        self
+    }
+
+    /// Prune the transfer functions before instanciating them
+    pub fn pruning(mut self, pruning: Pruning) -> Self {
+        self.pruning = Some(pruning);
+        self
+    }
+
+    /// Trace or load the scene and instanciate the simulation, ready to `start`
+    pub fn build(&self) -> Result<Simulation> {
+        let config = &self.scene.tracer;
+        let mut sim = match (&self.solution, &self.channel) {
+            (Some(_), Some(_)) => return Err(Error::ChannelWithSolution),
+            (Some(path), None) => {
+                let expected = crate::fingerprint::description_hash(&self.scene, &self.dir, config)?;
+                Simulation::try_load_solution(path, expected)?
+            }
+            (None, Some(channel)) => {
+                let mut sim = Simulation::new(self.scene.build(&self.dir)?);
+                sim.config = config.clone();
+                channels::apply(&mut sim.descriptor, channel);
+                sim
+            }
+            (None, None) => {
+                let mut sim = Simulation::new(self.scene.build(&self.dir)?);
+                sim.config = config.clone();
+                let times = self.scene.times();
+                if times.is_empty() {
+                    sim.try_solve()?;
+                } else {
+                    sim.try_solve_timeline(&times, |time| Ok(self.scene.build_at(&self.dir, time)?))?;
+                }
+                sim
+            }
+        };
+        sim.config = config.clone();
+        if let Some(pruning) = self.pruning {
+            sim.prune(pruning);
+        }
+        sim.try_instanciate()?;
+        Ok(sim)
+    }
+
+    /// Build the simulation and run it
+    pub fn run(self) -> Result<Simulation> {
+        let mut sim = self.build()?;
+        let tracked = self
+            .tracked
+            .unwrap_or_else(|| sim.descriptor.names.clone());
+        sim.try_start(tracked, self.beats)?;
+        Ok(sim)
+    }
+}
+
+/// Validate the scene and trace it, with the warnings of the validation
+fn trace(
+    world: &mut WorldDescriptor,
+    config: &TracerConfig,
+) -> Result<(TracerReport, Vec<Issue>)> {
+    if let Some(ref part) = config.part {
+        if let Some(name) = part.emitters.iter().find(|x| !world.names.contains(*x)) {
+            return Err(Error::UnknownAntenna(name.clone()));
+        }
+    }
+    let issues = crate::validation::validate(world, config);
+    if issues.iter().any(|x| x.severity == Severity::Error) {
+        return Err(Error::InvalidScene(issues));
+    }
+
+    Ok((crate::waves::tracing(world, config), issues))
 }
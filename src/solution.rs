@@ -0,0 +1,365 @@
+// solution.rs
+//! Solution files, in RON or in a compact binary format.
+//!
+//! Binary layout, little-endian:
+//! - magic `TIPE2SOL`, format version (u32), flags (u32, see `COMPRESSED`)
+//! - length (u64) and RON text of the solution without its taps
+//! - for each receiver, in antenna order: the number of tap sets (u32), the
+//!   static transfers then one set per frame of the timeline. A set is the
+//!   number of emitters (u32) then, by emitter, the number of taps (u64) and
+//!   the taps.
+//!
+//! Uncompressed taps are a time (u64) and a gain (f32). Compressed taps are
+//! the difference with the previous time as a zigzag LEB128 varint, then the gain.
+
+use crate::antennas::{SerializableWorld, SignalEvent, SignalReceiver};
+use crate::waves::{TracerConfig, TracerReport};
+use crate::TIME_PER_BEAT;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use ron::ser::PrettyConfig;
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+const MAGIC: &[u8; 8] = b"TIPE2SOL";
+pub const VERSION: u32 = 1;
+/// Tap times are delta-encoded varints
+pub const COMPRESSED: u32 = 1;
+
+/// How a solution was produced
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Metadata {
+    /// Version of the crate that solved it
+    pub version: String,
+    /// RFC 3339
+    pub created: String,
+    pub tracer: TracerConfig,
+    /// Samples per second of the transfer functions
+    pub sample_rate: f32,
+    /// Seconds spent solving
+    pub duration: f64,
+    /// One report per frame of the timeline, a single one for a static scene
+    pub reports: Vec<TracerReport>,
+}
+
+impl Metadata {
+    pub fn new(tracer: &TracerConfig, duration: chrono::Duration, reports: Vec<TracerReport>) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            created: chrono::Utc::now().to_rfc3339(),
+            tracer: tracer.clone(),
+            sample_rate: 1.0 / TIME_PER_BEAT,
+            duration: duration.num_milliseconds() as f64 / 1000.0,
+            reports,
+        }
+    }
+}
+
+fn ron_pretty() -> PrettyConfig {
+    PrettyConfig {
+        new_line: "\n".to_owned(),
+        indentor: "\t".to_owned(),
+        ..Default::default()
+    }
+}
+
+fn invalid(message: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn write_varint(w: &mut impl Write, mut x: u64) -> io::Result<()> {
+    loop {
+        let byte = (x & 0x7f) as u8;
+        x >>= 7;
+        if x == 0 {
+            return w.write_u8(byte);
+        }
+        w.write_u8(byte | 0x80)?;
+    }
+}
+
+fn read_varint(r: &mut impl Read) -> io::Result<u64> {
+    let mut x = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = r.read_u8()?;
+        x |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(x);
+        }
+    }
+    Err(invalid("varint longer than 64 bits"))
+}
+
+fn write_taps(w: &mut impl Write, taps: &[SignalEvent], compress: bool) -> io::Result<()> {
+    w.write_u64::<LittleEndian>(taps.len() as u64)?;
+    let mut previous = 0i64;
+    for tap in taps {
+        if compress {
+            let delta = tap.time as i64 - previous;
+            write_varint(w, ((delta << 1) ^ (delta >> 63)) as u64)?;
+            previous = tap.time as i64;
+        } else {
+            w.write_u64::<LittleEndian>(tap.time as u64)?;
+        }
+        w.write_f32::<LittleEndian>(tap.gain)?;
+    }
+    Ok(())
+}
+
+fn read_taps(r: &mut impl Read, compress: bool) -> io::Result<Vec<SignalEvent>> {
+    let count = r.read_u64::<LittleEndian>()? as usize;
+    // The count is not trusted for the allocation, the file may be truncated
+    let mut taps = Vec::with_capacity(count.min(1 << 20));
+    let mut previous = 0i64;
+    for _ in 0..count {
+        let time = if compress {
+            let zigzag = read_varint(r)?;
+            previous += (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+            previous
+        } else {
+            r.read_u64::<LittleEndian>()? as i64
+        };
+        if time < 0 {
+            return Err(invalid("negative tap time"));
+        }
+        let gain = r.read_f32::<LittleEndian>()?;
+        taps.push(SignalEvent {
+            time: time as usize,
+            gain,
+        });
+    }
+    Ok(taps)
+}
+
+fn write_set(w: &mut impl Write, set: &[Vec<SignalEvent>], compress: bool) -> io::Result<()> {
+    w.write_u32::<LittleEndian>(set.len() as u32)?;
+    for taps in set {
+        write_taps(w, taps, compress)?;
+    }
+    Ok(())
+}
+
+fn read_set(r: &mut impl Read, compress: bool) -> io::Result<Vec<Vec<SignalEvent>>> {
+    let count = r.read_u32::<LittleEndian>()?;
+    (0..count).map(|_| read_taps(r, compress)).collect()
+}
+
+pub fn write_ron(world: &SerializableWorld, path: &str) -> io::Result<()> {
+    let data = ron::ser::to_string_pretty(world, ron_pretty()).map_err(invalid)?;
+    std::fs::write(path, data)
+}
+
+pub fn read_ron(path: &str) -> io::Result<SerializableWorld> {
+    ron::de::from_reader(BufReader::new(File::open(path)?)).map_err(invalid)
+}
+
+pub fn write_binary(world: &SerializableWorld, path: &str, compress: bool) -> io::Result<()> {
+    // The taps are written in binary after the rest of the solution
+    let receivers = world
+        .receivers
+        .iter()
+        .map(|x| {
+            x.as_ref().map(|x| SignalReceiver {
+                position: x.position,
+                transfers: Vec::new(),
+                paths: x.paths.clone(),
+                frames: Vec::new(),
+                kind: x.kind.clone(),
+            })
+        })
+        .collect();
+    let description = SerializableWorld {
+        emitters: world.emitters.clone(),
+        receivers,
+        names: world.names.clone(),
+        scene_hash: world.scene_hash,
+        timeline: world.timeline.clone(),
+        metadata: world.metadata.clone(),
+    };
+    let description = ron::ser::to_string(&description).map_err(invalid)?;
+
+    let mut w = BufWriter::new(File::create(path)?);
+    w.write_all(MAGIC)?;
+    w.write_u32::<LittleEndian>(VERSION)?;
+    w.write_u32::<LittleEndian>(if compress { COMPRESSED } else { 0 })?;
+    w.write_u64::<LittleEndian>(description.len() as u64)?;
+    w.write_all(description.as_bytes())?;
+
+    for receiver in world.receivers.iter().flatten() {
+        w.write_u32::<LittleEndian>(1 + receiver.frames.len() as u32)?;
+        write_set(&mut w, &receiver.transfers, compress)?;
+        for frame in &receiver.frames {
+            write_set(&mut w, frame, compress)?;
+        }
+    }
+    w.flush()
+}
+
+/// Read a binary solution, streaming the taps from the file
+pub fn read_binary(path: &str) -> io::Result<SerializableWorld> {
+    let mut r = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a binary solution"));
+    }
+    let version = r.read_u32::<LittleEndian>()?;
+    if version > VERSION {
+        return Err(invalid(format!(
+            "solution format version {} is newer than the supported {}",
+            version, VERSION
+        )));
+    }
+    let compress = r.read_u32::<LittleEndian>()? & COMPRESSED != 0;
+
+    let length = r.read_u64::<LittleEndian>()?;
+    let mut description = String::new();
+    r.by_ref().take(length).read_to_string(&mut description)?;
+    if description.len() as u64 != length {
+        return Err(invalid("truncated solution"));
+    }
+    let mut world: SerializableWorld = ron::de::from_str(&description).map_err(invalid)?;
+
+    for receiver in world.receivers.iter_mut().flatten() {
+        let sets = r.read_u32::<LittleEndian>()?;
+        if sets == 0 {
+            return Err(invalid("receiver without transfers"));
+        }
+        receiver.transfers = read_set(&mut r, compress)?;
+        for _ in 1..sets {
+            receiver.frames.push(read_set(&mut r, compress)?);
+        }
+    }
+    Ok(world)
+}
+
+/// Read a solution in either format
+pub fn load(path: &str) -> io::Result<SerializableWorld> {
+    let mut magic = [0u8; 8];
+    let binary = match File::open(path)?.read_exact(&mut magic) {
+        Ok(()) => &magic == MAGIC,
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => false,
+        Err(e) => return Err(e),
+    };
+    if binary {
+        read_binary(path)
+    } else {
+        read_ron(path)
+    }
+}
+
+/// Write a solution, in RON if `path` ends with `.ron` and in compressed binary otherwise
+pub fn save(world: &SerializableWorld, path: &str) -> io::Result<()> {
+    if path.ends_with(".ron") {
+        write_ron(world, path)
+    } else {
+        write_binary(world, path, true)
+    }
+}
+
+/// Convert a solution between RON and binary, the format of `output` following `save`
+pub fn convert(input: &str, output: &str, compress: bool) -> io::Result<()> {
+    let world = load(input)?;
+    if output.ends_with(".ron") {
+        write_ron(&world, output)
+    } else {
+        write_binary(&world, output, compress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::antennas::SignalEmitter;
+    use crate::simulation::{EmissionKind, ReceptionKind};
+    use crate::test_utils::TempFile;
+    use nalgebra::Point3;
+
+    fn taps(x: &[(usize, f32)]) -> Vec<SignalEvent> {
+        x.iter().map(|&(time, gain)| SignalEvent { time, gain }).collect()
+    }
+
+    /// A receiver and an emitter over a timeline of two frames
+    fn world() -> SerializableWorld {
+        let receiver = SignalReceiver {
+            position: Point3::new(1.0, 2.0, 3.0),
+            transfers: vec![Vec::new(), taps(&[(3, 0.5), (1000, -0.25)])],
+            paths: Vec::new(),
+            frames: vec![
+                vec![Vec::new(), taps(&[(3, 0.5), (1000, -0.25)])],
+                vec![Vec::new(), taps(&[(7, 1.0), (1 << 40, 2.0)])],
+            ],
+            kind: ReceptionKind::OFDM,
+        };
+        let emitter = SignalEmitter {
+            position: Point3::origin(),
+            max_power: 1.0,
+            kind: EmissionKind::OFDM(vec![1, 2, 3]),
+        };
+        SerializableWorld {
+            emitters: vec![None, Some(emitter)],
+            receivers: vec![Some(receiver), None],
+            names: vec!["rec".to_owned(), "emit".to_owned()],
+            scene_hash: Some(0x0123_4567_89ab_cdef),
+            timeline: vec![0.0, 0.5],
+            metadata: None,
+        }
+    }
+
+    fn text(world: &SerializableWorld) -> String {
+        ron::ser::to_string(world).unwrap()
+    }
+
+    #[test]
+    fn varint() {
+        for &x in &[0, 1, 127, 128, 300, u64::from(u32::MAX), u64::MAX] {
+            let mut data = Vec::new();
+            write_varint(&mut data, x).unwrap();
+            assert_eq!(read_varint(&mut &data[..]).unwrap(), x);
+        }
+        assert!(read_varint(&mut &[0xffu8; 10][..]).is_err());
+    }
+
+    #[test]
+    fn unsorted_taps() {
+        // Deltas may be negative
+        let x = taps(&[(10, 1.0), (2, 2.0), (2, 3.0), (50, 4.0)]);
+        for &compress in &[true, false] {
+            let mut data = Vec::new();
+            write_taps(&mut data, &x, compress).unwrap();
+            let pairs = |x: &[SignalEvent]| -> Vec<(usize, f32)> {
+                x.iter().map(|x| (x.time, x.gain)).collect()
+            };
+            assert_eq!(pairs(&read_taps(&mut &data[..], compress).unwrap()), pairs(&x));
+        }
+    }
+
+    #[test]
+    fn formats() {
+        let world = world();
+        let (compressed, raw) = (TempFile::new("world.sol"), TempFile::new("raw.sol"));
+        for &(file, compress) in &[(&compressed, true), (&raw, false)] {
+            write_binary(&world, file.path(), compress).unwrap();
+            assert_eq!(text(&load(file.path()).unwrap()), text(&world));
+        }
+        let file = TempFile::new("world.ron");
+        save(&world, file.path()).unwrap();
+        assert_eq!(text(&load(file.path()).unwrap()), text(&world));
+
+        // The compressed taps are smaller
+        let size = |file: &TempFile| std::fs::metadata(file.path()).unwrap().len();
+        assert!(size(&compressed) < size(&raw));
+    }
+
+    #[test]
+    fn truncated() {
+        let file = TempFile::new("truncated.sol");
+        write_binary(&world(), file.path(), true).unwrap();
+        let data = std::fs::read(file.path()).unwrap();
+        std::fs::write(file.path(), &data[..data.len() - 3]).unwrap();
+        assert!(load(file.path()).is_err());
+        std::fs::write(file.path(), &data[..20]).unwrap();
+        assert!(read_binary(file.path()).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+// statistics.rs
+//! Standard metrics of the channel of each link, to compare scenes.
+//!
+//! As in `coverage`, the power of a tap is the absolute value of its gain,
+//! the energy of the rays that arrived at that time.
+
+use crate::antennas::{SignalEvent, WorldDescriptor};
+use crate::{TIME_PER_BEAT, WAVE_VELOCITY};
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Metrics of the transfer function from `emitter` to `receiver`, delays in seconds
+#[derive(Clone, Debug)]
+pub struct LinkStatistics {
+    pub receiver: String,
+    pub emitter: String,
+    pub taps: usize,
+    /// Received power over the power of the emitter, in dB
+    pub path_loss: f32,
+    /// Power-weighted mean delay after the first tap
+    pub mean_excess_delay: f32,
+    pub rms_delay_spread: f32,
+    /// Delay after the first tap of the last tap above the threshold
+    pub max_excess_delay: f32,
+    /// For a frequency correlation of 0.5, `1 / (5 * rms_delay_spread)`, in Hz.
+    /// Infinite for a single tap.
+    pub coherence_bandwidth: f32,
+    /// Power of the line-of-sight tap over the power of the others, in dB.
+    /// Minus infinity without a line of sight.
+    pub k_factor: f32,
+}
+
+/// Statistics of one transfer function sorted by time, with the power of its
+/// emitter and its line-of-sight delay in beats. The max excess delay counts
+/// the taps at most `threshold` dB below the strongest.
+pub fn transfer_statistics(
+    taps: &[SignalEvent],
+    max_power: f32,
+    line_of_sight: (usize, usize),
+    threshold: f32,
+) -> Option<LinkStatistics> {
+    let first = taps.first()?.time;
+    let power: f32 = taps.iter().map(|x| x.gain.abs()).sum();
+    if power == 0.0 {
+        return None;
+    }
+
+    let excess = |x: &SignalEvent| (x.time - first) as f32 * TIME_PER_BEAT;
+    let mean = taps.iter().map(|x| x.gain.abs() * excess(x)).sum::<f32>() / power;
+    let square = taps.iter().map(|x| x.gain.abs() * excess(x).powi(2)).sum::<f32>() / power;
+    let spread = (square - mean * mean).max(0.0).sqrt();
+
+    let strongest = taps.iter().map(|x| x.gain.abs()).fold(0.0, f32::max);
+    let floor = strongest * 10f32.powf(-threshold / 10.0);
+    let last = taps.iter().rev().find(|x| x.gain.abs() >= floor).map_or(0.0, excess);
+
+    // Nothing arrives before the direct path, a tap at its delay is the line of sight
+    let (earliest, latest) = line_of_sight;
+    let direct = taps
+        .iter()
+        .filter(|x| earliest <= x.time && x.time <= latest)
+        .map(|x| x.gain.abs())
+        .fold(0.0, f32::max);
+
+    Some(LinkStatistics {
+        receiver: String::new(),
+        emitter: String::new(),
+        taps: taps.len(),
+        path_loss: 10.0 * (max_power / power).log10(),
+        mean_excess_delay: mean,
+        rms_delay_spread: spread,
+        max_excess_delay: last,
+        coherence_bandwidth: 1.0 / (5.0 * spread),
+        k_factor: 10.0 * (direct / (power - direct)).log10(),
+    })
+}
+
+/// Statistics of every link with a transfer function, see `transfer_statistics`
+pub fn statistics(
+    world: &WorldDescriptor,
+    receiver_radius: f32,
+    threshold: f32,
+) -> Vec<LinkStatistics> {
+    let mut links = Vec::new();
+    for (r, receiver) in world.receivers.iter().enumerate() {
+        let receiver = match receiver {
+            Some(x) => x,
+            None => continue,
+        };
+        for (e, taps) in receiver.transfers.iter().enumerate() {
+            let emitter = match world.emitters.get(e) {
+                Some(Some(x)) => x,
+                _ => continue,
+            };
+            // The rays stop on the sphere of the receiver
+            let distance = (receiver.position - emitter.position).norm();
+            let beat = |d: f32| (d.max(0.0) / (WAVE_VELOCITY * TIME_PER_BEAT)).floor() as usize;
+            let line_of_sight = (
+                beat(distance - receiver_radius).saturating_sub(1),
+                beat(distance + receiver_radius) + 1,
+            );
+            if let Some(mut link) =
+                transfer_statistics(taps, emitter.max_power, line_of_sight, threshold)
+            {
+                link.receiver = world.names[r].clone();
+                link.emitter = world.names[e].clone();
+                links.push(link);
+            }
+        }
+    }
+    links
+}
+
+pub fn write_csv(links: &[LinkStatistics], path: &str) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(
+        file,
+        "receiver,emitter,taps,path_loss,mean_excess_delay,rms_delay_spread,max_excess_delay,coherence_bandwidth,k_factor"
+    )?;
+    for x in links {
+        writeln!(
+            file,
+            "{},{},{},{},{},{},{},{},{}",
+            x.receiver,
+            x.emitter,
+            x.taps,
+            x.path_loss,
+            x.mean_excess_delay,
+            x.rms_delay_spread,
+            x.max_excess_delay,
+            x.coherence_bandwidth,
+            x.k_factor
+        )?;
+    }
+    file.flush()
+}
+
+/// Aligned table of the links, delays in ns and bandwidths in MHz
+pub struct Table<'a>(pub &'a [LinkStatistics]);
+
+impl<'a> fmt::Display for Table<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<16} {:<16} {:>6} {:>10} {:>10} {:>10} {:>10} {:>10} {:>8}",
+            "receiver", "emitter", "taps", "loss dB", "mean ns", "rms ns", "max ns", "Bc MHz", "K dB"
+        )?;
+        for x in self.0 {
+            writeln!(
+                f,
+                "{:<16} {:<16} {:>6} {:>10.1} {:>10.2} {:>10.2} {:>10.2} {:>10.1} {:>8.1}",
+                x.receiver,
+                x.emitter,
+                x.taps,
+                x.path_loss,
+                x.mean_excess_delay * 1e9,
+                x.rms_delay_spread * 1e9,
+                x.max_excess_delay * 1e9,
+                x.coherence_bandwidth * 1e-6,
+                x.k_factor
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn taps(x: &[(usize, f32)]) -> Vec<SignalEvent> {
+        x.iter().map(|&(time, gain)| SignalEvent { time, gain }).collect()
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() <= 1e-3 * b.abs().max(1e-12)
+    }
+
+    #[test]
+    fn two_taps() {
+        let x = taps(&[(100, 1.0), (110, -1.0), (120, 0.0001)]);
+        let link = transfer_statistics(&x, 200.0, (99, 101), 30.0).unwrap();
+        assert_eq!(link.taps, 3);
+        assert!(close(link.path_loss, 20.0));
+        // Delays after the first tap, the third one weighing almost nothing
+        assert!(close(link.mean_excess_delay, 5.0 * TIME_PER_BEAT));
+        assert!(close(link.rms_delay_spread, 5.0 * TIME_PER_BEAT));
+        assert!(close(link.coherence_bandwidth, 1.0 / (25.0 * TIME_PER_BEAT)));
+        // The third tap is 40 dB below the others
+        assert!(close(link.max_excess_delay, 10.0 * TIME_PER_BEAT));
+        assert!(link.k_factor.abs() < 1e-3);
+    }
+
+    #[test]
+    fn single_tap() {
+        let link = transfer_statistics(&taps(&[(7, 0.5)]), 0.5, (0, 1), 30.0).unwrap();
+        assert_eq!(link.path_loss, 0.0);
+        assert_eq!(link.rms_delay_spread, 0.0);
+        assert_eq!(link.max_excess_delay, 0.0);
+        assert!(link.coherence_bandwidth.is_infinite());
+        // Nothing arrives at the delay of the direct path
+        assert_eq!(link.k_factor, f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn no_power() {
+        assert!(transfer_statistics(&[], 1.0, (0, 1), 30.0).is_none());
+        assert!(transfer_statistics(&taps(&[(3, 0.0)]), 1.0, (0, 1), 30.0).is_none());
+    }
+}
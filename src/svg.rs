@@ -0,0 +1,213 @@
+// svg.rs
+//! Top-down view of a scene for reports: footprints of the obstacles seen
+//! from above (projection on the (x, z) plane), antennas and the strongest
+//! propagation paths recorded by the tracer.
+
+use crate::antennas::WorldDescriptor;
+use crate::export::{self, Part, Swatch};
+
+use nalgebra::{Point2, Point3};
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Width of the drawing in pixels, the height follows the scene
+const WIDTH: f32 = 1000.0;
+const MARGIN: f32 = 5.0;
+/// Stroke width of the strongest path of each link, in pixels
+const MAX_STROKE: f32 = 4.0;
+const FONT_SIZE: f32 = 12.0;
+
+fn hex(colour: [f32; 3]) -> String {
+    let c = |x: f32| (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", c(colour[0]), c(colour[1]), c(colour[2]))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Convex hull of points in the plane (monotone chain), counter-clockwise
+fn hull(mut points: Vec<Point2<f32>>) -> Vec<Point2<f32>> {
+    points.sort_by(|a, b| {
+        (a.x, a.y)
+            .partial_cmp(&(b.x, b.y))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    let cross = |o: Point2<f32>, a: Point2<f32>, b: Point2<f32>| {
+        (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x)
+    };
+    let mut res: Vec<Point2<f32>> = Vec::with_capacity(2 * points.len());
+    for pass in 0..2 {
+        let start = res.len();
+        let ordered: Box<dyn Iterator<Item = &Point2<f32>>> = if pass == 0 {
+            Box::new(points.iter())
+        } else {
+            Box::new(points.iter().rev())
+        };
+        for &p in ordered {
+            while res.len() >= start + 2
+                && cross(res[res.len() - 2], res[res.len() - 1], p) <= 0.0
+            {
+                res.pop();
+            }
+            res.push(p);
+        }
+        res.pop();
+    }
+    res
+}
+
+fn project(p: &Point3<f32>) -> Point2<f32> {
+    Point2::new(p.x, p.z)
+}
+
+/// Write a top-down view of `world` to `path`. Infinite planes such as the
+/// ground are not drawn. When `paths` is not 0, the `paths` strongest
+/// recorded paths of each link are drawn, with a width proportional to their
+/// gain relative to the strongest one.
+pub fn write_svg(world: &WorldDescriptor, path: &str, paths: usize) -> io::Result<()> {
+    let (parts, swatches) = export::scene_parts(world);
+
+    // Footprints, the highest objects drawn last
+    let finite = |i: usize| {
+        let aabb = &world.collisions[i].1;
+        (aabb.maxs() - aabb.mins()).iter().all(|x| x.is_finite() && *x < 1e6)
+    };
+    let mut footprints: Vec<(f32, &Part, Vec<Point2<f32>>)> = parts
+        .iter()
+        .filter_map(|part| part.object.filter(|&i| finite(i)).map(|i| (i, part)))
+        .map(|(i, part)| {
+            let points = part
+                .mesh
+                .points
+                .iter()
+                .map(|p| project(&(part.transform * p)))
+                .collect();
+            (world.collisions[i].1.maxs().y, part, hull(points))
+        })
+        .collect();
+    footprints.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let antennas: Vec<(&str, Point2<f32>, bool)> = world
+        .names
+        .iter()
+        .enumerate()
+        .filter_map(|(a, name)| match (&world.emitters[a], &world.receivers[a]) {
+            (Some(emitter), _) => Some((name.as_str(), project(&emitter.position), true)),
+            (_, Some(receiver)) => Some((name.as_str(), project(&receiver.position), false)),
+            _ => None,
+        })
+        .collect();
+
+    // Drawing bounds in meters
+    let (mut min, mut max) = (
+        Point2::new(f32::INFINITY, f32::INFINITY),
+        Point2::new(f32::NEG_INFINITY, f32::NEG_INFINITY),
+    );
+    let points = footprints
+        .iter()
+        .flat_map(|x| x.2.iter().cloned())
+        .chain(antennas.iter().map(|x| x.1));
+    for p in points {
+        min = Point2::new(min.x.min(p.x), min.y.min(p.y));
+        max = Point2::new(max.x.max(p.x), max.y.max(p.y));
+    }
+    // Nothing to draw
+    if min.x > max.x {
+        min = Point2::new(-1.0, -1.0);
+        max = Point2::new(1.0, 1.0);
+    }
+    let min = min - nalgebra::Vector2::new(MARGIN, MARGIN);
+    let max = max + nalgebra::Vector2::new(MARGIN, MARGIN);
+    let scale = WIDTH / (max.x - min.x);
+    let height = (max.y - min.y) * scale;
+    let px = |p: Point2<f32>| ((p.x - min.x) * scale, (p.y - min.y) * scale);
+
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(
+        file,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        WIDTH, height, WIDTH, height
+    )?;
+    writeln!(file, r#"<rect width="100%" height="100%" fill="white"/>"#)?;
+
+    writeln!(file, r#"<g stroke="black" stroke-width="0.5">"#)?;
+    for (_, part, polygon) in &footprints {
+        let points: Vec<String> = polygon
+            .iter()
+            .map(|&p| {
+                let (x, y) = px(p);
+                format!("{:.1},{:.1}", x, y)
+            })
+            .collect();
+        let Swatch { ref name, colour } = swatches[part.swatch];
+        writeln!(
+            file,
+            r#"<polygon points="{}" fill="{}"><title>{} ({})</title></polygon>"#,
+            points.join(" "),
+            hex(colour),
+            escape(&part.name),
+            escape(name)
+        )?;
+    }
+    writeln!(file, "</g>")?;
+
+    if paths > 0 {
+        writeln!(
+            file,
+            r#"<g fill="none" stroke="orange" stroke-opacity="0.7" stroke-linejoin="round">"#
+        )?;
+        for receiver in world.receivers.iter().flatten() {
+            for link in &receiver.paths {
+                let strongest = link.first().map(|x| x.gain.abs()).unwrap_or(0.0);
+                for recorded in link.iter().take(paths) {
+                    let points: Vec<String> = recorded
+                        .points
+                        .iter()
+                        .chain(std::iter::once(&receiver.position))
+                        .map(|p| {
+                            let (x, y) = px(project(p));
+                            format!("{:.1},{:.1}", x, y)
+                        })
+                        .collect();
+                    writeln!(
+                        file,
+                        r#"<polyline points="{}" stroke-width="{:.2}"/>"#,
+                        points.join(" "),
+                        (MAX_STROKE * recorded.gain.abs() / strongest).max(0.2)
+                    )?;
+                }
+            }
+        }
+        writeln!(file, "</g>")?;
+    }
+
+    writeln!(file, r#"<g font-family="sans-serif" font-size="{}">"#, FONT_SIZE)?;
+    for (name, position, emitter) in &antennas {
+        let (x, y) = px(*position);
+        // The first swatches are the ones of emitters and receivers
+        let colour = hex(swatches[if *emitter { 0 } else { 1 }].colour);
+        writeln!(
+            file,
+            r#"<circle cx="{:.1}" cy="{:.1}" r="4" fill="{}"/><text x="{:.1}" y="{:.1}">{}</text>"#,
+            x,
+            y,
+            colour,
+            x + 6.0,
+            y - 6.0,
+            escape(name)
+        )?;
+    }
+    writeln!(file, "</g>")?;
+    writeln!(file, "</svg>")?;
+    Ok(())
+}
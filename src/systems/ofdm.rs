@@ -11,9 +11,9 @@ const SYMBOL_DURATION: usize = 2048;
 fn generate_wavetables() -> [[f32; SYMBOL_DURATION]; CARRIER_GROUP_SIZE] {
     let mut res = [[0.0; SYMBOL_DURATION]; CARRIER_GROUP_SIZE];
     let df = 1.0 / SYMBOL_DURATION as f32;
-    for k in 0..CARRIER_GROUP_SIZE {
-        for i in 0..SYMBOL_DURATION {
-            res[k][i] = (2.0 * PI * df * ((k + 1) as f32) * (i as f32)).sin();
+    for (k, table) in res.iter_mut().enumerate() {
+        for (i, x) in table.iter_mut().enumerate() {
+            *x = (2.0 * PI * df * ((k + 1) as f32) * (i as f32)).sin();
         }
     }
     res
@@ -31,22 +31,20 @@ impl OFDMEmitter {
     pub fn new(signal: &[u8]) -> OFDMEmitter {
         let mut global_vec = BitVec::from_bytes(signal);
 
-        while global_vec.len() % CARRIER_GROUP_SIZE != 0 {
+        while !global_vec.len().is_multiple_of(CARRIER_GROUP_SIZE) {
             global_vec.push(false);
         }
 
-        println!("{:?}", global_vec);
-
         let mut data_buffer: [BitVec; CARRIER_GROUP_SIZE] = Default::default();
 
-        for k in 0..CARRIER_GROUP_SIZE {
-            data_buffer[k] = BitVec::with_capacity(global_vec.len() / CARRIER_GROUP_SIZE);
+        for buffer in data_buffer.iter_mut() {
+            *buffer = BitVec::with_capacity(global_vec.len() / CARRIER_GROUP_SIZE);
         }
 
         let mut pos = 0;
         while pos < global_vec.len() {
-            for k in 0..CARRIER_GROUP_SIZE {
-                data_buffer[k].push(global_vec[pos]);
+            for buffer in data_buffer.iter_mut() {
+                buffer.push(global_vec[pos]);
                 pos += 1;
             }
         }
@@ -77,6 +75,12 @@ pub struct OFDMReceiver {
     fft: Arc<dyn FFT<f32>>,
 }
 
+impl Default for OFDMReceiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl OFDMReceiver {
     pub fn new() -> OFDMReceiver {
         OFDMReceiver {
@@ -14,12 +14,13 @@ impl Component for Emission {
 pub struct Reception {
     pub current: f32,
     receive_buffer: RingBuffer<f32>,
-    pub transfer: Vec<(Entity, Vec<SignalEvent>, usize)>,
+    /// Transfer functions by emitter, one per frame of the timeline
+    pub transfer: Vec<(Entity, Vec<Vec<SignalEvent>>, usize)>,
     pub label: String,
 }
 
 impl Reception {
-    pub fn new(transfer: Vec<(Entity, Vec<SignalEvent>, usize)>, name: impl ToString) -> Self {
+    pub fn new(transfer: Vec<(Entity, Vec<Vec<SignalEvent>>, usize)>, name: impl ToString) -> Self {
         Self {
             current: 0.0,
             receive_buffer: RingBuffer::with_capacity(
@@ -40,12 +41,30 @@ impl Component for Reception {
     type Storage = VecStorage<Reception>;
 }
 
-struct UnsafePointer(*mut RingBuffer<f32>);
+pub struct PropagationSystem {
+    /// Beat at which each frame of the timeline starts, empty for a static scene
+    timeline: Vec<usize>,
+    beat: usize,
+}
 
-unsafe impl Send for UnsafePointer {}
-unsafe impl Sync for UnsafePointer {}
+impl PropagationSystem {
+    pub fn new(timeline: Vec<usize>) -> Self {
+        Self { timeline, beat: 0 }
+    }
 
-pub struct PropagationSystem;
+    /// Frame of the current beat and weight of the next one, the transfer
+    /// functions are interpolated linearly between frames
+    fn frame(&self) -> (usize, f32) {
+        match self.timeline.iter().position(|&x| x > self.beat) {
+            None => (self.timeline.len().saturating_sub(1), 0.0),
+            Some(0) => (0, 0.0),
+            Some(k) => {
+                let (start, end) = (self.timeline[k - 1], self.timeline[k]);
+                (k - 1, (self.beat - start) as f32 / (end - start) as f32)
+            }
+        }
+    }
+}
 
 impl<'a> System<'a> for PropagationSystem {
     type SystemData = (ReadStorage<'a, Emission>, WriteStorage<'a, Reception>);
@@ -53,35 +72,56 @@ impl<'a> System<'a> for PropagationSystem {
     fn run(&mut self, (emission, mut reception): Self::SystemData) {
         use rayon::prelude::ParallelIterator;
         use specs::ParJoin;
+        let (frame, weight) = self.frame();
         (&mut reception,).par_join().for_each(|(rec,)| {
-            for (entity, events, max_time) in rec.transfer.iter() {
-                if let Some(emit) = emission.get(*entity) {
-                    // Parallel version
-                    /*
-                    use rayon::iter::IntoParallelIterator;
-                    let ptr = UnsafePointer(&mut rec.receive_buffer);
-                    unsafe {
-                        events.into_iter().for_each(|e| {
-                            *(*ptr.0).get_mut(e.time).unwrap_or_else(|| {
-                                panic!(
-                                    "Unreachable: sample not allocated, max_time: {}, capacity: {}",
-                                    max_time,
-                                    rec.receive_buffer.len(),
-                                )
-                            }) += emit.current * e.gain;
-                        });
-                    }*/
-
-                    // Sequential version
-                    for e in events.into_iter() {
-                        *rec.receive_buffer.get_mut(e.time).unwrap_or_else(|| {
-                            panic!("Unreachable: sample not allocated, max_time: {}", max_time)
-                        }) += emit.current * e.gain;
+            for (entity, frames, max_time) in rec.transfer.iter() {
+                // An entry without frames has nothing to propagate
+                let last = frames.len().checked_sub(1);
+                if let (Some(emit), Some(last)) = (emission.get(*entity), last) {
+                    let next = (frame + 1).min(last);
+                    for &(events, w) in &[(&frames[frame], 1.0 - weight), (&frames[next], weight)] {
+                        if w == 0.0 {
+                            continue;
+                        }
+                        for e in events.iter() {
+                            *rec.receive_buffer.get_mut(e.time).unwrap_or_else(|| {
+                                panic!("Unreachable: sample not allocated, max_time: {}", max_time)
+                            }) += emit.current * e.gain * w;
+                        }
                     }
                 }
             }
 
             rec.current = rec.receive_buffer.pop();
         });
+        self.beat += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PropagationSystem;
+
+    fn at(timeline: Vec<usize>, beat: usize) -> (usize, f32) {
+        PropagationSystem { timeline, beat }.frame()
+    }
+
+    #[test]
+    fn static_scene() {
+        assert_eq!(at(vec![], 0), (0, 0.0));
+        assert_eq!(at(vec![], 100), (0, 0.0));
+    }
+
+    #[test]
+    fn interpolation() {
+        let timeline = vec![10, 20, 40];
+        // Held at the first frame before it starts, at the last one after
+        assert_eq!(at(timeline.clone(), 0), (0, 0.0));
+        assert_eq!(at(timeline.clone(), 10), (0, 0.0));
+        assert_eq!(at(timeline.clone(), 15), (0, 0.5));
+        assert_eq!(at(timeline.clone(), 20), (1, 0.0));
+        assert_eq!(at(timeline.clone(), 35), (1, 0.75));
+        assert_eq!(at(timeline.clone(), 40), (2, 0.0));
+        assert_eq!(at(timeline, 1000), (2, 0.0));
     }
 }
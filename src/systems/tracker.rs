@@ -1,24 +1,34 @@
 use super::moving::ProxyReception;
 use super::propagation::{Emission, Reception};
+use crate::error::{Error, Result};
 use byteorder::{LittleEndian, WriteBytesExt};
 use specs::prelude::*;
 use std::collections::HashMap;
 
+/// File the signal of the antenna `name` is written to, as little-endian f32
+pub fn output_path(name: &str) -> String {
+    format!("output/{}.bin", name)
+}
+
 pub struct TrackerSystem {
     files: HashMap<String, std::fs::File>,
 }
 
 impl TrackerSystem {
     pub fn new(names: Vec<String>) -> Self {
+        Self::try_new(names).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Create `output/<name>.bin` for each antenna to track
+    pub fn try_new(names: Vec<String>) -> Result<Self> {
         let mut files = HashMap::new();
-        std::fs::create_dir("output").ok();
+        std::fs::create_dir_all("output").map_err(|e| Error::io("output", e))?;
         for name in names {
-            files.insert(
-                name.clone(),
-                std::fs::File::create(format!("output/{}.bin", name)).expect("Invalid file"),
-            );
+            let path = output_path(&name);
+            let file = std::fs::File::create(&path).map_err(|e| Error::io(&path, e))?;
+            files.insert(name, file);
         }
-        Self { files }
+        Ok(Self { files })
     }
 }
 
@@ -31,21 +41,24 @@ impl<'a> System<'a> for TrackerSystem {
 
     fn run(&mut self, (recs, proxies, emits): Self::SystemData) {
         for rec in recs.join() {
-            let mut file = self.files.get(&rec.label).expect("Name not found");
-            file.write_f32::<LittleEndian>(rec.current)
-                .expect("Failed to write receive value in Tracker");
+            if let Some(mut file) = self.files.get(&rec.label) {
+                file.write_f32::<LittleEndian>(rec.current)
+                    .expect("Failed to write receive value in Tracker");
+            }
         }
 
         for proxy in proxies.join() {
-            let mut file = self.files.get(&proxy.label).expect("Name not found");
-            file.write_f32::<LittleEndian>(proxy.current)
-                .expect("Failed to write proxy value in Tracker");
+            if let Some(mut file) = self.files.get(&proxy.label) {
+                file.write_f32::<LittleEndian>(proxy.current)
+                    .expect("Failed to write proxy value in Tracker");
+            }
         }
 
         for emit in emits.join() {
-            let mut file = self.files.get(&emit.label).expect("Name not found");
-            file.write_f32::<LittleEndian>(emit.current)
-                .expect("Failed to write emit value in Tracker");
+            if let Some(mut file) = self.files.get(&emit.label) {
+                file.write_f32::<LittleEndian>(emit.current)
+                    .expect("Failed to write emit value in Tracker");
+            }
         }
     }
 }
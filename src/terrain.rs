@@ -0,0 +1,261 @@
+// terrain.rs
+//! Digital elevation models for outdoor scenes.
+//! The grid is laid out with x towards the east, y up and z towards the south,
+//! the north-west corner of the grid being at the origin.
+
+use crate::antennas::{create_bvt_tuple, SceneObject};
+use crate::error::invalid;
+use crate::materials::Material;
+
+use nalgebra::{Isometry3, Point3};
+use ncollide3d::bounding_volume::aabb::AABB;
+use ncollide3d::shape::TriMesh;
+
+use std::io::{self, BufRead, BufReader};
+
+pub struct HeightMap {
+    pub ncols: usize,
+    pub nrows: usize,
+    pub cellsize: f32,
+    pub heights: Vec<Option<f32>>, // Row-major, northernmost row first
+}
+
+impl HeightMap {
+    /// Load an ESRI ASCII grid (.asc)
+    pub fn from_esri_ascii(path: &str) -> io::Result<Self> {
+        let file = BufReader::new(std::fs::File::open(path)?);
+
+        let mut ncols = None;
+        let mut nrows = None;
+        let mut cellsize = None;
+        let mut nodata = None;
+        let mut heights = Vec::new();
+
+        for (i, line) in file.lines().enumerate() {
+            let line = line?;
+            let mut words = line.split_whitespace().peekable();
+            let key = match words.peek() {
+                Some(key) => key.to_lowercase(),
+                None => continue,
+            };
+
+            if key.starts_with(|c: char| c.is_alphabetic()) {
+                words.next();
+                let value = words
+                    .next()
+                    .ok_or_else(|| invalid(path, i, format!("missing value for {}", key)))?;
+                match key.as_str() {
+                    "ncols" => ncols = Some(value.parse().map_err(|e| invalid(path, i, e))?),
+                    "nrows" => nrows = Some(value.parse().map_err(|e| invalid(path, i, e))?),
+                    "cellsize" => {
+                        cellsize = Some(value.parse().map_err(|e| invalid(path, i, e))?)
+                    }
+                    "nodata_value" => {
+                        nodata = Some(value.parse::<f32>().map_err(|e| invalid(path, i, e))?)
+                    }
+                    // The grid is placed relative to its corner
+                    "xllcorner" | "yllcorner" | "xllcenter" | "yllcenter" => (),
+                    _ => return Err(invalid(path, i, format!("unknown header {}", key))),
+                }
+            } else {
+                for word in words {
+                    let h: f32 = word.parse().map_err(|e| invalid(path, i, e))?;
+                    heights.push(if Some(h) == nodata { None } else { Some(h) });
+                }
+            }
+        }
+
+        let missing = |name| invalid(path, 0, format!("missing {} header", name));
+        let map = HeightMap {
+            ncols: ncols.ok_or_else(|| missing("ncols"))?,
+            nrows: nrows.ok_or_else(|| missing("nrows"))?,
+            cellsize: cellsize.ok_or_else(|| missing("cellsize"))?,
+            heights,
+        };
+        map.check(path)?;
+        Ok(map)
+    }
+
+    /// Load a CSV file of heights, one row of the grid per line
+    pub fn from_csv(path: &str, cellsize: f32) -> io::Result<Self> {
+        let file = BufReader::new(std::fs::File::open(path)?);
+
+        let mut ncols = None;
+        let mut nrows = 0;
+        let mut heights = Vec::new();
+
+        for (i, line) in file.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let row = line
+                .split(',')
+                .map(|x| match x.trim() {
+                    "" | "nan" | "NaN" => Ok(None),
+                    x => x.parse().map(Some).map_err(|e| invalid(path, i, e)),
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+
+            if *ncols.get_or_insert(row.len()) != row.len() {
+                return Err(invalid(path, i, "rows have different lengths"));
+            }
+            heights.extend(row);
+            nrows += 1;
+        }
+
+        let map = HeightMap {
+            ncols: ncols.unwrap_or(0),
+            nrows,
+            cellsize,
+            heights,
+        };
+        map.check(path)?;
+        Ok(map)
+    }
+
+    fn check(&self, path: &str) -> io::Result<()> {
+        if self.ncols < 2 || self.nrows < 2 {
+            return Err(invalid(path, 0, "the grid needs at least 2 rows and 2 columns"));
+        }
+        if self.heights.len() != self.ncols * self.nrows {
+            return Err(invalid(
+                path,
+                0,
+                format!(
+                    "expected {} heights, found {}",
+                    self.ncols * self.nrows,
+                    self.heights.len()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn height(&self, col: usize, row: usize) -> Option<f32> {
+        self.heights[row * self.ncols + col]
+    }
+
+    /// Two triangles per cell, facing up. Cells touching missing data are left out.
+    pub fn to_trimesh(&self) -> TriMesh<f32> {
+        let points = (0..self.nrows)
+            .flat_map(|row| (0..self.ncols).map(move |col| (col, row)))
+            .map(|(col, row)| {
+                Point3::new(
+                    col as f32 * self.cellsize,
+                    self.height(col, row).unwrap_or(0.0),
+                    row as f32 * self.cellsize,
+                )
+            })
+            .collect();
+
+        let mut indices = Vec::with_capacity((self.ncols - 1) * (self.nrows - 1) * 2);
+        for row in 0..self.nrows - 1 {
+            for col in 0..self.ncols - 1 {
+                let corners = [(col, row), (col + 1, row), (col, row + 1), (col + 1, row + 1)];
+                if corners.iter().any(|&(c, r)| self.height(c, r).is_none()) {
+                    continue;
+                }
+
+                let id = |(c, r): (usize, usize)| r * self.ncols + c;
+                let (nw, ne, sw, se) = (
+                    id(corners[0]),
+                    id(corners[1]),
+                    id(corners[2]),
+                    id(corners[3]),
+                );
+                indices.push(Point3::new(nw, sw, ne));
+                indices.push(Point3::new(ne, sw, se));
+            }
+        }
+
+        TriMesh::new(points, indices, None)
+    }
+}
+
+/// Ground made of `map`, `transform` placing its north-west corner
+pub fn terrain(
+    map: &HeightMap,
+    transform: Isometry3<f32>,
+    material: Material,
+) -> (SceneObject, AABB<f32>) {
+    create_bvt_tuple(&map.to_trimesh(), transform, material)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempFile;
+
+    #[test]
+    fn esri_ascii() {
+        let file = TempFile::with(
+            "grid.asc",
+            "ncols 3\nNROWS 2\nxllcorner 100\nyllcorner 200\ncellsize 5\nnodata_value -9999\n\
+             1 2 3\n4 -9999 6\n",
+        );
+        let map = HeightMap::from_esri_ascii(file.path()).unwrap();
+        assert_eq!((map.ncols, map.nrows, map.cellsize), (3, 2, 5.0));
+        assert_eq!(map.height(2, 0), Some(3.0));
+        assert_eq!(map.height(1, 1), None);
+        // Both cells touch the missing height
+        assert_eq!(map.to_trimesh().faces().len(), 0);
+    }
+
+    #[test]
+    fn esri_ascii_errors() {
+        let short = TempFile::with("short.asc", "ncols 2\nnrows 2\ncellsize 1\n1 2\n3\n");
+        assert!(HeightMap::from_esri_ascii(short.path()).is_err());
+        let header = TempFile::with("header.asc", "ncols 2\nnrows 2\n1 2\n3 4\n");
+        assert!(HeightMap::from_esri_ascii(header.path()).is_err());
+        let unknown =
+            TempFile::with("unknown.asc", "ncols 2\nnrows 2\ncellsize 1\nfoo 3\n1 2\n3 4\n");
+        let error = HeightMap::from_esri_ascii(unknown.path()).err().unwrap();
+        assert!(error.to_string().ends_with(":4: unknown header foo"));
+    }
+
+    #[test]
+    fn csv() {
+        let file = TempFile::with("grid.csv", "0, 1, 2\n\n3, nan, 5\n6, 7, 8\n");
+        let map = HeightMap::from_csv(file.path(), 2.0).unwrap();
+        assert_eq!((map.ncols, map.nrows), (3, 3));
+        assert_eq!(map.height(1, 1), None);
+        assert_eq!(map.height(2, 2), Some(8.0));
+        // Four cells, all touching the missing centre height
+        let mesh = map.to_trimesh();
+        assert_eq!(mesh.faces().len(), 0);
+        assert_eq!(mesh.points()[8], Point3::new(4.0, 8.0, 4.0));
+
+        let ragged = TempFile::with("ragged.csv", "0,1\n2\n");
+        assert!(HeightMap::from_csv(ragged.path(), 1.0).is_err());
+    }
+
+    #[test]
+    fn mesh() {
+        let file = TempFile::with("flat.csv", "0,0,0\n0,0,0\n");
+        let mesh = HeightMap::from_csv(file.path(), 1.0).unwrap().to_trimesh();
+        assert_eq!(mesh.faces().len(), 4);
+        // Facing up
+        for i in 0..mesh.faces().len() {
+            let t = mesh.triangle_at(i);
+            let normal = (t.b() - t.a()).cross(&(t.c() - t.a()));
+            assert!(normal.y > 0.0);
+        }
+    }
+
+    #[test]
+    fn placement() {
+        let file = TempFile::with("placed.csv", "0,1\n2,3\n");
+        let map = HeightMap::from_csv(file.path(), 10.0).unwrap();
+        // A quarter turn around y sends the east edge of the grid north
+        let transform = Isometry3::new(
+            nalgebra::Vector3::new(100.0, -5.0, 0.0),
+            nalgebra::Vector3::y() * std::f32::consts::FRAC_PI_2,
+        );
+        let (_, aabb) = terrain(&map, transform, Material::soil());
+        let close = |a: &Point3<f32>, b: Point3<f32>| (a - b).norm() < 1e-4;
+        assert!(close(aabb.mins(), Point3::new(100.0, -5.0, -10.0)));
+        assert!(close(aabb.maxs(), Point3::new(110.0, -2.0, 0.0)));
+    }
+}
@@ -0,0 +1,31 @@
+// test_utils.rs
+//! Helpers shared by the unit tests
+
+/// File in the temporary directory, removed when dropped
+pub struct TempFile(String);
+
+impl TempFile {
+    /// Path for `name`, unique to this process, with nothing written yet
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("tipe2-{}-{}", std::process::id(), name));
+        TempFile(path.to_str().unwrap().to_owned())
+    }
+
+    /// Temporary file holding `content`
+    pub fn with(name: &str, content: impl AsRef<[u8]>) -> Self {
+        let file = Self::new(name);
+        std::fs::write(&file.0, content).unwrap();
+        file
+    }
+
+    pub fn path(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // Tests that fail early may not have written it
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
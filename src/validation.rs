@@ -0,0 +1,242 @@
+// validation.rs
+//! Geometric checks of a scene before it is traced. Antennas inside obstacles
+//! and interpenetrating volumes break the refractive index bookkeeping of
+//! `waves::process` without any visible error.
+
+use crate::antennas::{SceneObject, WorldDescriptor};
+use crate::geometry::Primitive;
+use crate::waves::TracerConfig;
+
+use nalgebra::Unit;
+use ncollide3d::bounding_volume::BoundingVolume;
+use ncollide3d::query;
+use ncollide3d::shape::Plane;
+
+use std::fmt;
+
+/// Penetration depth under which touching objects are not reported
+const OVERLAP_TOLERANCE: f32 = 0.001;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Indices in `WorldDescriptor::collisions`
+    pub objects: Vec<usize>,
+    /// Index in `WorldDescriptor::names`
+    pub antenna: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}", severity, self.message)
+    }
+}
+
+fn same_material(a: &SceneObject, b: &SceneObject) -> bool {
+    a.material() == b.material() && a.blockage == b.blockage
+}
+
+// The negated comparisons also catch NaN
+#[allow(clippy::neg_cmp_op_on_partial_ord)]
+fn degenerate(primitive: &Primitive) -> Option<&'static str> {
+    let positive = |values: &[f32]| values.iter().all(|&x| x > 0.0 && x.is_finite());
+    match *primitive {
+        Primitive::Plane { normal } if !(normal.norm() > 0.0) => Some("plane without normal"),
+        Primitive::Cuboid { half_extents } if !positive(half_extents.as_slice()) => {
+            Some("cuboid with a null or negative extent")
+        }
+        Primitive::Ball { radius } if !positive(&[radius]) => Some("ball with a null radius"),
+        Primitive::Cylinder {
+            half_height,
+            radius,
+        }
+        | Primitive::Cone {
+            half_height,
+            radius,
+        } if !positive(&[half_height, radius]) => Some("cylinder or cone with a null size"),
+        Primitive::Capsule { radius, .. } if !positive(&[radius]) => {
+            Some("capsule with a null radius")
+        }
+        Primitive::ConvexHull { ref points } if points.len() < 4 => {
+            Some("convex hull with less than 4 points")
+        }
+        Primitive::TriMesh { ref indices, .. } if indices.is_empty() => Some("empty mesh"),
+        _ => None,
+    }
+}
+
+/// Check the obstacles of `world` and the position of its antennas.
+/// Receivers closer than the receiver radius of `config` to an obstacle are reported.
+pub fn validate(world: &WorldDescriptor, config: &TracerConfig) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let objects: Vec<(usize, &SceneObject)> = world
+        .collisions
+        .iter()
+        .map(|(object, _)| object)
+        .enumerate()
+        .filter(|(_, object)| object.receiver.is_none())
+        .collect();
+    let primitives: Vec<Primitive> = objects
+        .iter()
+        .map(|(_, object)| object.geometry().primitive())
+        .collect();
+
+    for (&(i, _), primitive) in objects.iter().zip(&primitives) {
+        if let Some(message) = degenerate(primitive) {
+            issues.push(Issue {
+                severity: Severity::Error,
+                objects: vec![i],
+                antenna: None,
+                message: format!("object {}: {}", i, message),
+            });
+        }
+    }
+
+    // Antennas
+    for (a, name) in world.names.iter().enumerate() {
+        let (position, margin) = match (&world.emitters[a], &world.receivers[a]) {
+            (Some(emitter), _) => (emitter.position, 0.0),
+            (_, Some(receiver)) => (receiver.position, config.receiver_radius),
+            _ => continue,
+        };
+
+        for (&(i, object), primitive) in objects.iter().zip(&primitives) {
+            let inside = match *primitive {
+                // The surface of a mesh does not delimit a volume
+                Primitive::TriMesh { .. } => false,
+                _ => object
+                    .geometry()
+                    .contains_point(object.transform(), &position),
+            };
+            let distance = object
+                .geometry()
+                .distance_to_point(object.transform(), &position, false);
+
+            if inside {
+                issues.push(Issue {
+                    // Going through a blockage volume is the point of blockages
+                    severity: if object.blockage.is_some() {
+                        Severity::Warning
+                    } else {
+                        Severity::Error
+                    },
+                    objects: vec![i],
+                    antenna: Some(a),
+                    message: format!("antenna {} ({}) is inside object {}", a, name, i),
+                });
+            } else if distance < margin {
+                issues.push(Issue {
+                    severity: Severity::Warning,
+                    objects: vec![i],
+                    antenna: Some(a),
+                    message: format!(
+                        "receiver {} ({}) is {:.3} m from object {}, closer than its radius",
+                        a, name, distance, i
+                    ),
+                });
+            }
+        }
+    }
+
+    // Interpenetrating volumes, only convex shapes and planes can be checked
+    for (k, &(i, a)) in objects.iter().enumerate() {
+        for (l, &(j, b)) in objects.iter().enumerate().skip(k + 1) {
+            if same_material(a, b) || !world.collisions[i].1.intersects(&world.collisions[j].1) {
+                continue;
+            }
+
+            let (ga, gb) = (a.geometry(), b.geometry());
+            let contact = match (
+                &primitives[k],
+                &primitives[l],
+                ga.as_support_map(),
+                gb.as_support_map(),
+            ) {
+                (_, _, Some(sa), Some(sb)) => query::contact_support_map_support_map(
+                    a.transform(),
+                    sa,
+                    b.transform(),
+                    sb,
+                    0.0,
+                ),
+                (&Primitive::Plane { normal }, _, _, Some(sb)) => query::contact_plane_support_map(
+                    a.transform(),
+                    &Plane::new(Unit::new_normalize(normal)),
+                    b.transform(),
+                    sb,
+                    0.0,
+                ),
+                (_, &Primitive::Plane { normal }, Some(sa), _) => query::contact_support_map_plane(
+                    a.transform(),
+                    sa,
+                    b.transform(),
+                    &Plane::new(Unit::new_normalize(normal)),
+                    0.0,
+                ),
+                _ => None,
+            };
+
+            if let Some(contact) = contact.filter(|c| c.depth > OVERLAP_TOLERANCE) {
+                issues.push(Issue {
+                    severity: Severity::Error,
+                    objects: vec![i, j],
+                    antenna: None,
+                    message: format!(
+                        "objects {} and {} overlap by {:.3} m with different materials",
+                        i, j, contact.depth
+                    ),
+                });
+            }
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::SceneDescription;
+
+    /// Every shipped scene builds, at each instant of its timeline, without errors
+    #[test]
+    fn shipped_scenes() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes");
+        let mut paths: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|x| x.unwrap().path())
+            .filter(|x| x.extension().is_some_and(|x| x == "ron"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+
+        let mut failures = Vec::new();
+        for path in paths {
+            let (scene, dir) = SceneDescription::load(path.to_str().unwrap()).unwrap();
+            let times = scene.times();
+            for time in if times.is_empty() { vec![0.0] } else { times } {
+                match scene.build_at(&dir, time) {
+                    Ok(world) => failures.extend(
+                        validate(&world, &scene.tracer)
+                            .into_iter()
+                            .filter(|x| x.severity == Severity::Error)
+                            .map(|x| format!("{} at {} s: {}", path.display(), time, x)),
+                    ),
+                    Err(e) => failures.push(format!("{} at {} s: {}", path.display(), time, e)),
+                }
+            }
+        }
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    }
+}
@@ -1,6 +1,7 @@
 // waves.rs
-#![allow(dead_code)]
-use crate::antennas::{SignalEvent, WorldDescriptor};
+use crate::antennas::{PropagationPath, SignalEvent, WorldDescriptor};
+use crate::atmosphere::Atmosphere;
+use crate::MAX_FREQUENCY;
 use crate::TIME_PER_BEAT;
 use crate::WAVE_VELOCITY;
 use crossbeam_channel as channel;
@@ -27,27 +28,84 @@ use nalgebra::{Isometry3, Translation3};
 use ncollide3d::partitioning::BVH;
 use ncollide3d::shape::Ball;
 
-use std::mem;
-
-use crate::constants::refractive_indices;
+use crate::materials::AIR_INDEX;
 
 use std::collections::BTreeMap;
 
+use serde::{Deserialize, Serialize};
+
 const NB_SAMPLE: u32 = 10_000;
 const NB_SAMPLEF: f32 = NB_SAMPLE as f32;
+pub const RAYS_PER_EMITTER: usize = (NB_SAMPLE * NB_SAMPLE) as usize;
 
 const PI: f32 = std::f32::consts::PI;
 const MIN_GAIN: f32 = 0.001;
 const BOUNCE_MARGIN: f32 = 0.00001;
-pub const ABSORBANCE_AIR: f32 = 0.0001;
+pub const RECEIVER_RADIUS: f32 = 0.5;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct TracerConfig {
+    /// Radius of the ball standing for each receiver in the scene
+    pub receiver_radius: f32,
+    pub atmosphere: Atmosphere,
+    /// Strongest paths kept per link, 0 to skip recording them
+    pub paths: usize,
+    /// Seed of the random choices between reflection and refraction, drawn
+    /// at each solve when absent
+    pub seed: Option<u64>,
+    /// Part of the solve traced by this job, the whole solve when absent.
+    /// Partial solutions are combined by `merge`.
+    pub part: Option<Part>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Part {
+    /// Names of the emitters traced, all of them when empty
+    pub emitters: Vec<String>,
+    /// Indices `[start, end)` of the rays traced from each emitter, out of
+    /// `RAYS_PER_EMITTER`, all of them when absent
+    pub rays: Option<(u64, u64)>,
+}
+
+/// What a solve did, kept in the metadata of solutions
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TracerReport {
+    pub seed: u64,
+    pub rays: usize,
+    /// Rays traced from each emitter, indexed by antenna
+    #[serde(default)]
+    pub emitter_rays: Vec<usize>,
+    /// Energy leaving each emitter, indexed by antenna
+    pub emitted: Vec<f32>,
+    /// Energy reaching each receiver from all emitters, indexed by antenna
+    pub received: Vec<f32>,
+}
+
+impl Default for TracerConfig {
+    fn default() -> Self {
+        Self {
+            receiver_radius: RECEIVER_RADIUS,
+            atmosphere: Atmosphere::default(),
+            paths: 0,
+            seed: None,
+            part: None,
+        }
+    }
+}
 
-/// (Ray,energy,distance,max_energy,n)
+/// (Ray,energy,distance,max_energy,n,path)
 struct EnergyRay {
     ray: Ray<f32>,
     energy: f32,
     distance: f32,
     max_energy: f32,
     n: f32,
+    /// Interaction points, when paths are recorded
+    path: Option<Vec<Point3<f32>>>,
+    /// State of the random stream of the ray
+    rng: u64,
 }
 
 struct Output {
@@ -55,15 +113,68 @@ struct Output {
     idr: usize,
     time: usize,
     energy: f32,
+    path: Option<Vec<Point3<f32>>>,
+}
+
+/// splitmix64, cheap enough to give each ray its own random stream
+fn splitmix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Uniform in [0, 1)
+fn uniform(state: &mut u64) -> f32 {
+    (splitmix(state) >> 40) as f32 / (1u64 << 24) as f32
+}
+
+/// Sum of the weights of the rays `[start, end)` of an emitter, see `emit`
+fn emission_weight((start, end): (u64, u64)) -> f32 {
+    let samples = u64::from(NB_SAMPLE);
+    let n = f64::from(NB_SAMPLE);
+    // The weight of a ray is the product of a term in phi and one in theta
+    let mut thetas = vec![0.0f64; NB_SAMPLE as usize + 1];
+    for beta in 0..NB_SAMPLE as usize {
+        let theta = beta as f64 * std::f64::consts::PI / n;
+        thetas[beta + 1] = thetas[beta] + 1.0 - (theta / 2.0).cos();
+    }
+
+    let end = end.min(samples * samples);
+    if end <= start {
+        return 0.0;
+    }
+    let mut sum = 0.0;
+    for alpha in start / samples..end.div_ceil(samples) {
+        let phi = alpha as f64 * 2.0 * std::f64::consts::PI / n;
+        let first = start.max(alpha * samples) - alpha * samples;
+        let last = end.min((alpha + 1) * samples) - alpha * samples;
+        sum += phi * (thetas[last as usize] - thetas[first as usize]);
+    }
+    (2.0 * sum / (n * n)) as f32
 }
 
 /// Do the ray tracing and populate emitters with receivers
-pub fn tracing(world: &mut WorldDescriptor) {
+pub fn tracing(world: &mut WorldDescriptor, config: &TracerConfig) -> TracerReport {
+    let seed = config.seed.unwrap_or_else(rand::random);
+    let all = (0, RAYS_PER_EMITTER as u64);
+    let part = config.part.clone().unwrap_or_default();
+    let range = part.rays.unwrap_or(all);
+    let selected: Vec<bool> = world
+        .emitters
+        .iter()
+        .zip(&world.names)
+        .map(|(x, name)| {
+            x.is_some() && (part.emitters.is_empty() || part.emitters.contains(name))
+        })
+        .collect();
     let threadpool = ThreadPoolBuilder::new()
         .stack_size(64 * 1024usize.pow(2))
         .build()
         .unwrap();
-    let ball = Ball::new(0.5f32);
+    let ball = Ball::new(config.receiver_radius);
+    let air_absorbance = config.atmosphere.absorbance(MAX_FREQUENCY);
 
     for (i, receiver) in world
         .receivers
@@ -85,27 +196,39 @@ pub fn tracing(world: &mut WorldDescriptor) {
         ))
     }
 
-    let collisions = BVT::new_balanced(mem::replace(&mut world.collisions, vec![]));
+    let collisions = BVT::new_balanced(std::mem::take(&mut world.collisions));
+    let record = config.paths > 0;
+    if record {
+        for receiver in world.receivers.iter_mut().filter_map(|x| x.as_mut()) {
+            receiver.paths = vec![Vec::new(); world.emitters.len()];
+        }
+    }
 
     let (so, ro) = channel::bounded(10_000);
 
     threadpool.scope(|s| {
-        let ref emitters = world.emitters;
+        let emitters = &world.emitters;
 
         // Starting rays
+        let selected = &selected;
         let rays = emitters
             .into_par_iter()
             .enumerate()
+            .filter(move |(i, _)| selected[*i])
             .filter_map(|(i, x)| x.as_ref().map(|x| (i, x)))
             .flat_map(|(ide, x)| {
-                emit((x.position).clone())
-                    .map(move |ray| (ray.0, x.max_power * ray.1, 0.))
+                emit(x.position)
+                    .filter(move |x| x.0 >= range.0 && x.0 < range.1)
+                    .map(move |(index, ray, weight)| (index, ray, x.max_power * weight, 0.))
                     .map(move |ray| (ide, ray))
             });
         
         // Processing
         s.spawn(move |_s| {
-            rays.map(|(ide, (ray, energy, distance))| {
+            rays.map(|(ide, (index, ray, energy, distance))| {
+                let path = if record { Some(vec![ray.origin]) } else { None };
+                let mut rng = seed ^ ((ide as u64) << 40) ^ index;
+                splitmix(&mut rng);
                 (
                     ide,
                     EnergyRay {
@@ -113,7 +236,9 @@ pub fn tracing(world: &mut WorldDescriptor) {
                         energy,
                         distance,
                         max_energy: energy,
-                        n: *refractive_indices::AIR,
+                        n: AIR_INDEX,
+                        path,
+                        rng,
                     },
                 )
             })
@@ -121,7 +246,7 @@ pub fn tracing(world: &mut WorldDescriptor) {
                 let ide = x.0;
                 let mut ray = x.1;
                 let mut iter = 0;
-                while let Some((new_ray, new_iter)) = process((ide, ray), &so, &collisions, iter) {
+                while let Some((new_ray, new_iter)) = process((ide, ray), &so, &collisions, air_absorbance, iter) {
                     ray = new_ray;
                     iter = new_iter;
                 }
@@ -130,13 +255,31 @@ pub fn tracing(world: &mut WorldDescriptor) {
 
         // Collecting
         for out in ro {
-            world.receivers[out.idr].as_mut().unwrap().transfers[out.ide].push(SignalEvent {
+            let receiver = world.receivers[out.idr].as_mut().unwrap();
+            receiver.transfers[out.ide].push(SignalEvent {
                 time: out.time,
                 gain: out.energy,
             });
+            if let Some(points) = out.path {
+                let paths = &mut receiver.paths[out.ide];
+                paths.push(PropagationPath {
+                    points,
+                    time: out.time,
+                    gain: out.energy,
+                });
+                if paths.len() >= 2 * config.paths {
+                    strongest(paths, config.paths);
+                }
+            }
         }
     });
 
+    for receiver in world.receivers.iter_mut().filter_map(|x| x.as_mut()) {
+        for paths in &mut receiver.paths {
+            strongest(paths, config.paths);
+        }
+    }
+
     for receiver in world.receivers.iter_mut().filter_map(|x| x.as_mut()) {
         for transfers in &mut receiver.transfers {
             let res = transfers.iter().fold(BTreeMap::new(), |mut acc, x| {
@@ -150,6 +293,39 @@ pub fn tracing(world: &mut WorldDescriptor) {
                 .collect();
         }
     }
+
+    let weight = emission_weight(range);
+    let traced = (range.1.min(all.1).saturating_sub(range.0)) as usize;
+    let emitter_rays: Vec<usize> = selected.iter().map(|&x| if x { traced } else { 0 }).collect();
+    TracerReport {
+        seed,
+        rays: emitter_rays.iter().sum(),
+        emitter_rays,
+        emitted: world
+            .emitters
+            .iter()
+            .zip(&selected)
+            .map(|(x, &selected)| match *x {
+                Some(ref x) if selected => x.max_power * weight,
+                _ => 0.0,
+            })
+            .collect(),
+        received: world
+            .receivers
+            .iter()
+            .map(|x| {
+                x.as_ref().map_or(0.0, |x| {
+                    x.transfers.iter().flatten().map(|e| e.gain.abs()).sum()
+                })
+            })
+            .collect(),
+    }
+}
+
+/// Keep the `count` paths of highest gain, strongest first
+fn strongest(paths: &mut Vec<PropagationPath>, count: usize) {
+    paths.sort_by(|a, b| b.gain.abs().partial_cmp(&a.gain.abs()).unwrap());
+    paths.truncate(count);
 }
 
 // TODO: Dephasage refraction
@@ -157,6 +333,7 @@ fn process(
     (ide, energyray): (usize, EnergyRay),
     out: &channel::Sender<Output>,
     bvs: &BVT<SceneObject, AABB<f32>>,
+    air_absorbance: f32,
     rec: usize,
 ) -> Option<(EnergyRay, usize)> {
     if (energyray.energy / energyray.max_energy).abs() < MIN_GAIN {
@@ -168,19 +345,71 @@ fn process(
         .map(|(id, r)| (bvs.content(id).1.expect("no data in node"), r))
     {
         let dist_plus = (energyray.ray.dir * inter.1.toi).norm() * energyray.n;
+        let hit = energyray.ray.origin + energyray.ray.dir * inter.1.toi;
+
+        // Receivers and blockages do not deviate rays
+        let mut path = energyray.path;
+        if inter.0.receiver.is_none() && inter.0.blockage.is_none() {
+            if let Some(ref mut path) = path {
+                path.push(hit);
+            }
+        }
+
+        // Blockage volumes are crossed in a straight line
+        if let Some(ref blockage) = inter.0.blockage {
+            let dir = energyray.ray.dir.normalize();
+            // A ray from an antenna inside the volume only finds the face it leaves by
+            let (depth, beyond, exit) = if inter
+                .0
+                .geometry()
+                .contains_point(inter.0.transform(), &energyray.ray.origin)
+            {
+                ((hit - energyray.ray.origin).norm(), 0.0, hit)
+            } else {
+                let inside = Ray::new(hit + dir * BOUNCE_MARGIN, dir);
+                let depth = inter.0.cast(&inside).map(|x| x.toi).unwrap_or(0.0);
+                (depth, depth, inside.origin + dir * depth)
+            };
+            let energy = energyray.energy
+                * (-air_absorbance * dist_plus).exp()
+                * blockage.gain(depth, MAX_FREQUENCY);
+
+            return Some((
+                EnergyRay {
+                    ray: Ray::new(exit + dir * BOUNCE_MARGIN, dir),
+                    energy,
+                    distance: energyray.distance + dist_plus + beyond,
+                    max_energy: energyray.max_energy,
+                    n: energyray.n,
+                    path,
+                    rng: energyray.rng,
+                },
+                rec + 1,
+            ));
+        }
+
         let mut n2 = inter.0.n;
         let mut energy = energyray.energy;
-        if n2 == energyray.n {
-            n2 = *refractive_indices::AIR;
-            energy = energy * (-inter.0.absorbance * dist_plus as f32).exp();
+        // A ray reaching a receiver comes from the air, not from inside the ball
+        if inter.0.receiver.is_some() {
+            energy *= (-air_absorbance * dist_plus).exp();
+        } else if n2 == energyray.n {
+            n2 = AIR_INDEX;
+            energy *= (-inter.0.absorbance * dist_plus).exp();
         } else {
-            energy = energy * (-ABSORBANCE_AIR * dist_plus as f32).exp();
+            energy *= (-air_absorbance * dist_plus).exp();
         }
         let n1 = energyray.n;
 
-        let rand: f32 = rand::random();
+        let mut rng = energyray.rng;
+        let rand = uniform(&mut rng);
 
         let normal = inter.1.normal.normalize();
+        // Rough surfaces scatter part of the reflected energy, which is not traced
+        let specular = inter
+            .0
+            .material()
+            .specular_factor(normal.dot(&energyray.ray.dir.normalize()).abs());
 
         // If the ray crossed an antenna, record the encounter
         if let Some(idr) = inter.0.receiver {
@@ -189,7 +418,12 @@ fn process(
                 idr,
                 time: ((energyray.distance + dist_plus) / (WAVE_VELOCITY * TIME_PER_BEAT)).floor()
                     as usize,
-                energy: energy,
+                energy,
+                path: path.as_ref().map(|path| {
+                    let mut path = path.clone();
+                    path.push(hit);
+                    path
+                }),
             });
         }
 
@@ -203,10 +437,12 @@ fn process(
 
             nextrays = Some(EnergyRay {
                 ray: reflection.translate_by(normal_l * BOUNCE_MARGIN),
-                energy: -energy,
+                energy: -energy * specular,
                 distance: energyray.distance + dist_plus,
                 max_energy: energyray.max_energy,
                 n: n1,
+                path,
+                rng,
             });
         } else {
             let cos1 = (normal.dot(&energyray.ray.dir) * normal - energyray.ray.dir).norm();
@@ -222,10 +458,12 @@ fn process(
 
                     nextrays = Some(EnergyRay {
                         ray: reflection.translate_by(normal_l * BOUNCE_MARGIN),
-                        energy: -energy,
+                        energy: -energy * specular,
                         distance: energyray.distance + dist_plus,
                         max_energy: energyray.max_energy,
                         n: n1,
+                        path,
+                        rng,
                     });
                 }
             } else {
@@ -235,10 +473,12 @@ fn process(
 
                 nextrays = Some(EnergyRay {
                     ray: refraction.0.translate_by(normal_l * BOUNCE_MARGIN),
-                    energy: energy,
+                    energy,
                     distance: energyray.distance + dist_plus,
                     max_energy: energyray.max_energy,
                     n: n2,
+                    path,
+                    rng,
                 });
             }
         }
@@ -251,10 +491,11 @@ fn process(
         return Some((nextrays, rec + 1));
         //}
     }
-    return None;
+    None
 }
 
-fn emit<'a>(pos: Point3<f32>) -> impl ParallelIterator<Item = (Ray<f32>, f32)> {
+/// Rays of an emitter at `pos`, with their index and weight
+fn emit(pos: Point3<f32>) -> impl ParallelIterator<Item = (u64, Ray<f32>, f32)> {
     (0..NB_SAMPLE).into_par_iter().flat_map(move |alpha| {
         (0..NB_SAMPLE).into_par_iter().map(move |beta| {
             let phi = alpha as f32 * 2f32 * PI / NB_SAMPLEF;
@@ -263,7 +504,8 @@ fn emit<'a>(pos: Point3<f32>) -> impl ParallelIterator<Item = (Ray<f32>, f32)> {
             let y = theta.sin() * phi.sin();
             let z = theta.cos();
             (
-                Ray::new(pos.clone(), Vector3::new(x, y, z).normalize()),
+                u64::from(alpha) * u64::from(NB_SAMPLE) + u64::from(beta),
+                Ray::new(pos, Vector3::new(x, y, z).normalize()),
                 2. * phi * (1. - (theta / 2.).cos()) / NB_SAMPLEF.powi(2),
             )
         })